@@ -10,7 +10,7 @@ use windows::Win32::System::{
     SystemServices::DLL_PROCESS_ATTACH,
     Threading::{CreateThread, THREAD_CREATION_FLAGS},
 };
-use windows::Win32::UI::WindowsAndMessaging::{CallWindowProcA, SetWindowLongPtrA, GWLP_WNDPROC};
+use windows::Win32::UI::WindowsAndMessaging::{CallWindowProcW, SetWindowLongPtrW, GWLP_WNDPROC};
 
 use retour::static_detour;
 
@@ -55,21 +55,13 @@ unsafe extern "system" fn h_wndproc(
     wparam: WPARAM,
     lparam: LPARAM,
 ) -> LRESULT {
-    if egui_glow_internal::is_init() {
-        let should_skip_wnd_proc = egui_glow_internal::on_event(umsg, wparam.0, lparam.0).unwrap();
+    let o_wndproc = std::mem::transmute(O_WNDPROC.unwrap());
 
-        if should_skip_wnd_proc {
-            return LRESULT(1);
-        }
+    if egui_glow_internal::is_init() {
+        return egui_glow_internal::wndproc(o_wndproc, hwnd, umsg, wparam, lparam);
     }
 
-    CallWindowProcA(
-        std::mem::transmute(O_WNDPROC.unwrap()),
-        hwnd,
-        umsg,
-        wparam,
-        lparam,
-    )
+    CallWindowProcW(o_wndproc, hwnd, umsg, wparam, lparam)
 }
 
 unsafe extern "system" fn extension_main(_dll: *mut c_void) -> u32 {
@@ -125,7 +117,7 @@ unsafe extern "system" fn extension_main(_dll: *mut c_void) -> u32 {
     let hdc = rx.recv().unwrap();
     let hwnd = WindowFromDC(hdc);
 
-    O_WNDPROC = Some(SetWindowLongPtrA(hwnd, GWLP_WNDPROC, h_wndproc as _));
+    O_WNDPROC = Some(SetWindowLongPtrW(hwnd, GWLP_WNDPROC, h_wndproc as _));
 
     0
 }
@@ -0,0 +1,246 @@
+use egui::{ColorImage, Id};
+use std::sync::{Arc, Mutex};
+
+#[cfg(windows)]
+use ::clipboard::{windows_clipboard::WindowsClipboardContext, ClipboardProvider};
+#[cfg(windows)]
+use windows::Win32::{
+    Foundation::{GlobalFree, HANDLE, HGLOBAL, HWND},
+    Graphics::Gdi::{BITMAPINFOHEADER, BI_BITFIELDS, BI_RGB},
+    System::{
+        DataExchange::{
+            CloseClipboard, EmptyClipboard, GetClipboardData, OpenClipboard, SetClipboardData,
+        },
+        Memory::{GlobalAlloc, GlobalLock, GlobalSize, GlobalUnlock, GMEM_MOVEABLE},
+        Ole::CF_DIB,
+    },
+};
+
+/// where copied text and images go and pasted ones come from, swap it out with set_clipboard
+pub trait Clipboard {
+    fn get_text(&mut self) -> Option<String>;
+
+    fn set_text(&mut self, text: &str);
+
+    fn get_image(&mut self) -> Option<ColorImage> {
+        None
+    }
+
+    fn set_image(&mut self, _image: &ColorImage) {}
+}
+
+/// keeps everything in the process, for tests and for hosts that shouldn't touch the system clipboard
+#[derive(Clone, Debug, Default)]
+pub struct MemoryClipboard {
+    pub text: Option<String>,
+    pub image: Option<ColorImage>,
+}
+
+impl Clipboard for MemoryClipboard {
+    fn get_text(&mut self) -> Option<String> {
+        self.text.clone()
+    }
+
+    fn set_text(&mut self, text: &str) {
+        self.text = Some(text.to_owned());
+    }
+
+    fn get_image(&mut self) -> Option<ColorImage> {
+        self.image.clone()
+    }
+
+    fn set_image(&mut self, image: &ColorImage) {
+        self.image = Some(image.clone());
+    }
+}
+
+/// the windows clipboard, text through the clipboard crate and images as CF_DIB
+#[cfg(windows)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClipboard;
+
+#[cfg(windows)]
+impl Clipboard for SystemClipboard {
+    fn get_text(&mut self) -> Option<String> {
+        WindowsClipboardContext.get_contents().ok()
+    }
+
+    fn set_text(&mut self, text: &str) {
+        let _ = WindowsClipboardContext.set_contents(text.to_owned());
+    }
+
+    fn get_image(&mut self) -> Option<ColorImage> {
+        unsafe {
+            OpenClipboard(HWND(0)).ok()?;
+            let image = get_dib();
+            let _ = CloseClipboard();
+
+            image
+        }
+    }
+
+    fn set_image(&mut self, image: &ColorImage) {
+        // nothing to put there, and set_dib can't split an empty image into rows
+        if image.width() == 0 || image.height() == 0 {
+            return;
+        }
+
+        unsafe {
+            if OpenClipboard(HWND(0)).is_err() {
+                return;
+            }
+            set_dib(image);
+            let _ = CloseClipboard();
+        }
+    }
+}
+
+/// the clipboard behind set_clipboard, also kept in egui's memory so the ui can get to it on any thread
+pub(crate) type SharedClipboard = Arc<Mutex<Box<dyn Clipboard + Send>>>;
+
+fn clipboard_id() -> Id {
+    Id::new("egui_glow_internal_clipboard")
+}
+
+pub(crate) fn share(
+    egui_ctx: &egui::Context,
+    clipboard: Box<dyn Clipboard + Send>,
+) -> SharedClipboard {
+    let shared = Arc::new(Mutex::new(clipboard));
+    egui_ctx.data_mut(|data| data.insert_temp(clipboard_id(), shared.clone()));
+
+    shared
+}
+
+/// runs f with the overlay's clipboard, for images and whatever else egui's own copy and paste don't cover.
+/// call it from the ui function with its context; None if the clipboard isn't there or a panic poisoned it
+pub fn with_clipboard<R>(
+    ctx: &egui::Context,
+    f: impl FnOnce(&mut dyn Clipboard) -> R,
+) -> Option<R> {
+    let shared = ctx.data(|data| data.get_temp::<SharedClipboard>(clipboard_id()))?;
+    let mut clipboard = shared.lock().ok()?;
+
+    Some(f(clipboard.as_mut()))
+}
+
+/// the default for init, the system clipboard where there is one
+pub(crate) fn default_clipboard() -> Box<dyn Clipboard + Send> {
+    #[cfg(windows)]
+    return Box::new(SystemClipboard);
+
+    #[cfg(not(windows))]
+    return Box::<MemoryClipboard>::default();
+}
+
+/// reads a 24 or 32 bit CF_DIB, the clipboard has to be open
+#[cfg(windows)]
+unsafe fn get_dib() -> Option<ColorImage> {
+    let memory = HGLOBAL(GetClipboardData(CF_DIB.0 as u32).ok()?.0 as _);
+    let data = GlobalLock(memory) as *const u8;
+    if data.is_null() {
+        return None;
+    }
+
+    let image = read_dib(std::slice::from_raw_parts(data, GlobalSize(memory)));
+    let _ = GlobalUnlock(memory);
+
+    image
+}
+
+#[cfg(windows)]
+fn read_dib(data: &[u8]) -> Option<ColorImage> {
+    let header_size = std::mem::size_of::<BITMAPINFOHEADER>();
+    if data.len() < header_size {
+        return None;
+    }
+
+    let header = unsafe { std::ptr::read_unaligned(data.as_ptr() as *const BITMAPINFOHEADER) };
+    let bytes_per_pixel = match (header.biBitCount, header.biCompression) {
+        (24, c) if c == BI_RGB.0 => 3,
+        (32, c) if c == BI_RGB.0 || c == BI_BITFIELDS.0 => 4,
+        // palettes and compressed formats aren't worth it for an overlay
+        _ => return None,
+    };
+
+    let width = header.biWidth.unsigned_abs() as usize;
+    let height = header.biHeight.unsigned_abs() as usize;
+    // rows are padded to 4 bytes, and go bottom up unless the height is negative
+    let stride = (width * bytes_per_pixel + 3) & !3;
+    let bottom_up = header.biHeight > 0;
+
+    // BI_BITFIELDS puts three color masks between the header and the pixels
+    let mut offset = header.biSize as usize;
+    if header.biCompression == BI_BITFIELDS.0 && header.biSize as usize == header_size {
+        offset += 12;
+    }
+    offset += header.biClrUsed as usize * 4;
+
+    let pixels = data.get(offset..offset + stride * height)?;
+
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        let row = if bottom_up { height - 1 - y } else { y };
+        let row = &pixels[row * stride..row * stride + width * bytes_per_pixel];
+
+        for pixel in row.chunks_exact(bytes_per_pixel) {
+            let alpha = if bytes_per_pixel == 4 { pixel[3] } else { 255 };
+            rgba.extend_from_slice(&[pixel[2], pixel[1], pixel[0], alpha]);
+        }
+    }
+
+    // most programs leave the alpha byte of 32 bit dibs at zero
+    if bytes_per_pixel == 4 && rgba.chunks_exact(4).all(|pixel| pixel[3] == 0) {
+        rgba.chunks_exact_mut(4).for_each(|pixel| pixel[3] = 255);
+    }
+
+    Some(ColorImage::from_rgba_unmultiplied([width, height], &rgba))
+}
+
+/// writes image as a bottom up 32 bit CF_DIB, the clipboard has to be open
+#[cfg(windows)]
+unsafe fn set_dib(image: &ColorImage) {
+    let header_size = std::mem::size_of::<BITMAPINFOHEADER>();
+    let [width, height] = image.size;
+
+    let Ok(memory) = GlobalAlloc(GMEM_MOVEABLE, header_size + width * height * 4) else {
+        return;
+    };
+
+    let data = GlobalLock(memory) as *mut u8;
+    if data.is_null() {
+        let _ = GlobalFree(memory);
+        return;
+    }
+
+    let header = BITMAPINFOHEADER {
+        biSize: header_size as u32,
+        biWidth: width as i32,
+        biHeight: height as i32,
+        biPlanes: 1,
+        biBitCount: 32,
+        biCompression: BI_RGB.0,
+        biSizeImage: (width * height * 4) as u32,
+        ..Default::default()
+    };
+    std::ptr::write_unaligned(data as *mut BITMAPINFOHEADER, header);
+
+    let pixels = std::slice::from_raw_parts_mut(data.add(header_size), width * height * 4);
+    for (y, row) in image.pixels.chunks_exact(width).enumerate() {
+        let start = (height - 1 - y) * width * 4;
+
+        for (x, color) in row.iter().enumerate() {
+            let [r, g, b, a] = color.to_srgba_unmultiplied();
+            pixels[start + x * 4..start + x * 4 + 4].copy_from_slice(&[b, g, r, a]);
+        }
+    }
+
+    let _ = GlobalUnlock(memory);
+
+    // the clipboard owns the memory once this works
+    if EmptyClipboard().is_err()
+        || SetClipboardData(CF_DIB.0 as u32, HANDLE(memory.0 as _)).is_err()
+    {
+        let _ = GlobalFree(memory);
+    }
+}
@@ -0,0 +1,164 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use windows::Win32::System::Performance::{QueryPerformanceCounter, QueryPerformanceFrequency};
+
+/// where egui's time comes from, swap it out with set_clock
+pub trait Clock {
+    /// seconds since some fixed point, never going backwards
+    fn now(&mut self) -> f64;
+}
+
+/// QueryPerformanceCounter, unaffected by ntp and the user changing the system time
+#[derive(Clone, Copy, Debug)]
+pub struct PerformanceClock {
+    frequency: i64,
+    start: i64,
+}
+
+impl PerformanceClock {
+    pub fn new() -> Self {
+        let mut frequency = 0;
+        let mut start = 0;
+
+        // can't fail on anything since windows xp
+        unsafe {
+            let _ = QueryPerformanceFrequency(&mut frequency);
+            let _ = QueryPerformanceCounter(&mut start);
+        }
+
+        Self {
+            frequency: frequency.max(1),
+            start,
+        }
+    }
+}
+
+impl Default for PerformanceClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for PerformanceClock {
+    fn now(&mut self) -> f64 {
+        let mut counter = 0;
+        unsafe {
+            let _ = QueryPerformanceCounter(&mut counter);
+        }
+
+        // counting from start keeps the f64 precise for the lifetime of the process
+        (counter - self.start) as f64 / self.frequency as f64
+    }
+}
+
+/// time only moves when told to, for deterministic tests. clones share the same time
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock {
+    seconds: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn set(&self, seconds: f64) {
+        self.seconds.store(seconds.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, seconds: f64) {
+        self.set(self.get() + seconds);
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.seconds.load(Ordering::Relaxed))
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&mut self) -> f64 {
+        self.get()
+    }
+}
+
+/// a smoothed average of the time between frames, for RawInput::predicted_dt
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct FrameTimer {
+    last_frame: Option<f64>,
+    average: f32,
+}
+
+impl Default for FrameTimer {
+    fn default() -> Self {
+        Self {
+            last_frame: None,
+            average: 1.0 / 60.0,
+        }
+    }
+}
+
+impl FrameTimer {
+    /// how much of each new interval goes into the average, lower is smoother but slower to follow changes
+    const SMOOTHING: f32 = 0.1;
+
+    /// records a frame at time and returns the new average
+    pub(crate) fn frame(&mut self, time: f64) -> f32 {
+        if let Some(last_frame) = self.last_frame {
+            // loading screens and breakpoints would throw the average off for seconds
+            let interval = ((time - last_frame) as f32).clamp(1.0 / 1000.0, 0.25);
+            self.average += (interval - self.average) * Self::SMOOTHING;
+        }
+
+        self.last_frame = Some(time);
+
+        self.average
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_clones_share_time() {
+        let clock = ManualClock::default();
+        let mut shared: Box<dyn Clock> = Box::new(clock.clone());
+
+        clock.set(2.0);
+        clock.advance(0.5);
+
+        assert_eq!(shared.now(), 2.5);
+    }
+
+    #[test]
+    fn frame_timer_follows_the_frame_rate() {
+        let clock = ManualClock::default();
+        let mut timer = FrameTimer::default();
+
+        // nothing to measure yet
+        assert_eq!(timer.frame(clock.get()), 1.0 / 60.0);
+
+        let mut average = 0.0;
+        for _ in 0..100 {
+            clock.advance(1.0 / 30.0);
+            average = timer.frame(clock.get());
+        }
+
+        assert!((average - 1.0 / 30.0).abs() < 0.001, "{average}");
+    }
+
+    #[test]
+    fn frame_timer_clamps_outliers() {
+        let clock = ManualClock::default();
+        let mut timer = FrameTimer::default();
+        timer.frame(clock.get());
+
+        // a ten second hitch counts as a quarter second
+        clock.advance(10.0);
+        let average = timer.frame(clock.get());
+        let expected = 1.0 / 60.0 + (0.25 - 1.0 / 60.0) * FrameTimer::SMOOTHING;
+        assert!((average - expected).abs() < 1e-6, "{average}");
+
+        // and two frames in the same instant as a millisecond apart
+        let before = timer.frame(clock.get());
+        assert!(before < average);
+    }
+}
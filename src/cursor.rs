@@ -0,0 +1,237 @@
+use egui::{
+    epaint::Mesh, Align2, Color32, CursorIcon, FontId, Painter, Pos2, Rect, Rounding, Shape,
+    Stroke, Vec2,
+};
+use windows::{core::PCWSTR, Win32::UI::WindowsAndMessaging::*};
+
+/// outline of a standard arrow cursor, tip at the origin
+const ARROW: [Vec2; 7] = [
+    Vec2::new(0.0, 0.0),
+    Vec2::new(0.0, 16.0),
+    Vec2::new(4.0, 12.0),
+    Vec2::new(7.0, 19.0),
+    Vec2::new(9.0, 18.0),
+    Vec2::new(6.0, 11.0),
+    Vec2::new(11.0, 11.0),
+];
+
+/// the arrow isn't convex, so it gets filled as triangles
+const ARROW_TRIANGLES: [[u32; 3]; 5] = [[0, 1, 2], [0, 2, 5], [0, 5, 6], [2, 3, 4], [2, 4, 5]];
+
+const FILL: Color32 = Color32::WHITE;
+const OUTLINE: Color32 = Color32::BLACK;
+
+/// draws a cursor sprite for icon with its hotspot at pos
+pub(crate) fn paint_cursor(painter: &Painter, pos: Pos2, icon: CursorIcon) {
+    match icon {
+        CursorIcon::None => {}
+        CursorIcon::Default | CursorIcon::ContextMenu | CursorIcon::Alias => {
+            paint_arrow(painter, pos)
+        }
+        CursorIcon::Help => {
+            paint_arrow(painter, pos);
+            paint_badge_text(painter, pos, "?");
+        }
+        CursorIcon::Copy => {
+            paint_arrow(painter, pos);
+            paint_badge_text(painter, pos, "+");
+        }
+        CursorIcon::Progress => {
+            paint_arrow(painter, pos);
+            paint_spinner(painter, pos + Vec2::new(15.0, 17.0), 4.0);
+        }
+        CursorIcon::Wait => paint_spinner(painter, pos, 7.0),
+        CursorIcon::PointingHand => paint_hand(painter, pos),
+        CursorIcon::Grab => paint_fist(painter, pos, true),
+        CursorIcon::Grabbing => paint_fist(painter, pos, false),
+        CursorIcon::Crosshair => paint_cross(painter, pos, 9.0),
+        CursorIcon::Cell => paint_cross(painter, pos, 6.0),
+        CursorIcon::Text => paint_beam(painter, pos, Vec2::Y),
+        CursorIcon::VerticalText => paint_beam(painter, pos, Vec2::X),
+        CursorIcon::Move | CursorIcon::AllScroll => {
+            paint_double_arrow(painter, pos, Vec2::X);
+            paint_double_arrow(painter, pos, Vec2::Y);
+        }
+        CursorIcon::NoDrop | CursorIcon::NotAllowed => paint_forbidden(painter, pos),
+        CursorIcon::ResizeHorizontal
+        | CursorIcon::ResizeEast
+        | CursorIcon::ResizeWest
+        | CursorIcon::ResizeColumn => paint_double_arrow(painter, pos, Vec2::X),
+        CursorIcon::ResizeVertical
+        | CursorIcon::ResizeNorth
+        | CursorIcon::ResizeSouth
+        | CursorIcon::ResizeRow => paint_double_arrow(painter, pos, Vec2::Y),
+        CursorIcon::ResizeNeSw | CursorIcon::ResizeNorthEast | CursorIcon::ResizeSouthWest => {
+            paint_double_arrow(painter, pos, Vec2::new(1.0, -1.0).normalized())
+        }
+        CursorIcon::ResizeNwSe | CursorIcon::ResizeNorthWest | CursorIcon::ResizeSouthEast => {
+            paint_double_arrow(painter, pos, Vec2::new(1.0, 1.0).normalized())
+        }
+        CursorIcon::ZoomIn => paint_magnifier(painter, pos, true),
+        CursorIcon::ZoomOut => paint_magnifier(painter, pos, false),
+    }
+}
+
+/// the closest system cursor for icon, None means the cursor should be hidden
+pub(crate) fn system_cursor(icon: CursorIcon) -> Option<PCWSTR> {
+    Some(match icon {
+        CursorIcon::None => return None,
+        CursorIcon::Default
+        | CursorIcon::ContextMenu
+        | CursorIcon::Alias
+        | CursorIcon::Copy
+        | CursorIcon::ZoomIn
+        | CursorIcon::ZoomOut => IDC_ARROW,
+        CursorIcon::Help => IDC_HELP,
+        CursorIcon::PointingHand => IDC_HAND,
+        CursorIcon::Progress => IDC_APPSTARTING,
+        CursorIcon::Wait => IDC_WAIT,
+        CursorIcon::Cell | CursorIcon::Crosshair => IDC_CROSS,
+        CursorIcon::Text | CursorIcon::VerticalText => IDC_IBEAM,
+        CursorIcon::NoDrop | CursorIcon::NotAllowed => IDC_NO,
+        CursorIcon::Move | CursorIcon::AllScroll | CursorIcon::Grab | CursorIcon::Grabbing => {
+            IDC_SIZEALL
+        }
+        CursorIcon::ResizeHorizontal
+        | CursorIcon::ResizeEast
+        | CursorIcon::ResizeWest
+        | CursorIcon::ResizeColumn => IDC_SIZEWE,
+        CursorIcon::ResizeVertical
+        | CursorIcon::ResizeNorth
+        | CursorIcon::ResizeSouth
+        | CursorIcon::ResizeRow => IDC_SIZENS,
+        CursorIcon::ResizeNeSw | CursorIcon::ResizeNorthEast | CursorIcon::ResizeSouthWest => {
+            IDC_SIZENESW
+        }
+        CursorIcon::ResizeNwSe | CursorIcon::ResizeNorthWest | CursorIcon::ResizeSouthEast => {
+            IDC_SIZENWSE
+        }
+    })
+}
+
+fn paint_arrow(painter: &Painter, pos: Pos2) {
+    let points: Vec<Pos2> = ARROW.iter().map(|offset| pos + *offset).collect();
+
+    let mut mesh = Mesh::default();
+    for point in &points {
+        mesh.colored_vertex(*point, FILL);
+    }
+    for [a, b, c] in ARROW_TRIANGLES {
+        mesh.add_triangle(a, b, c);
+    }
+
+    painter.add(Shape::mesh(mesh));
+    painter.add(Shape::closed_line(points, Stroke::new(1.0, OUTLINE)));
+}
+
+/// a line that stays visible on both dark and light backgrounds
+fn paint_line(painter: &Painter, a: Pos2, b: Pos2) {
+    painter.line_segment([a, b], Stroke::new(3.0, OUTLINE));
+    painter.line_segment([a, b], Stroke::new(1.0, FILL));
+}
+
+fn paint_badge_text(painter: &Painter, pos: Pos2, text: &str) {
+    let center = pos + Vec2::new(15.0, 17.0);
+
+    painter.circle(center, 5.0, FILL, Stroke::new(1.0, OUTLINE));
+    painter.text(
+        center,
+        Align2::CENTER_CENTER,
+        text,
+        FontId::monospace(9.0),
+        OUTLINE,
+    );
+}
+
+fn paint_spinner(painter: &Painter, center: Pos2, radius: f32) {
+    painter.circle_stroke(center, radius, Stroke::new(3.0, OUTLINE));
+    painter.circle_stroke(center, radius, Stroke::new(1.0, FILL));
+    paint_line(painter, center, center + Vec2::new(0.0, -radius * 0.7));
+}
+
+fn paint_cross(painter: &Painter, pos: Pos2, size: f32) {
+    paint_line(painter, pos - Vec2::X * size, pos + Vec2::X * size);
+    paint_line(painter, pos - Vec2::Y * size, pos + Vec2::Y * size);
+}
+
+/// an i-beam along dir
+fn paint_beam(painter: &Painter, pos: Pos2, dir: Vec2) {
+    let across = dir.rot90() * 3.0;
+    let top = pos - dir * 8.0;
+    let bottom = pos + dir * 8.0;
+
+    paint_line(painter, top, bottom);
+    paint_line(painter, top - across, top + across);
+    paint_line(painter, bottom - across, bottom + across);
+}
+
+/// a two-headed arrow along dir, centered on pos
+fn paint_double_arrow(painter: &Painter, pos: Pos2, dir: Vec2) {
+    let across = dir.rot90();
+
+    paint_line(painter, pos - dir * 6.0, pos + dir * 6.0);
+
+    for dir in [dir, -dir] {
+        let tip = pos + dir * 10.0;
+        let base = pos + dir * 5.0;
+
+        painter.add(Shape::convex_polygon(
+            vec![tip, base + across * 4.0, base - across * 4.0],
+            FILL,
+            Stroke::new(1.0, OUTLINE),
+        ));
+    }
+}
+
+fn paint_forbidden(painter: &Painter, pos: Pos2) {
+    let offset = Vec2::splat(7.0 * std::f32::consts::FRAC_1_SQRT_2);
+
+    painter.circle_stroke(pos, 7.0, Stroke::new(4.0, OUTLINE));
+    painter.circle_stroke(pos, 7.0, Stroke::new(2.0, FILL));
+    painter.line_segment([pos - offset, pos + offset], Stroke::new(4.0, OUTLINE));
+    painter.line_segment([pos - offset, pos + offset], Stroke::new(2.0, FILL));
+}
+
+/// a hand with the index finger pointing up, hotspot on the fingertip
+fn paint_hand(painter: &Painter, pos: Pos2) {
+    let finger = Rect::from_min_max(pos + Vec2::new(-2.0, 0.0), pos + Vec2::new(2.0, 10.0));
+    let palm = Rect::from_min_max(pos + Vec2::new(-5.0, 7.0), pos + Vec2::new(7.0, 18.0));
+
+    painter.rect(palm, Rounding::same(3.0), FILL, Stroke::new(1.0, OUTLINE));
+    painter.rect(finger, Rounding::same(2.0), FILL, Stroke::new(1.0, OUTLINE));
+}
+
+/// an open (grab) or closed (grabbing) hand, centered on pos
+fn paint_fist(painter: &Painter, pos: Pos2, open: bool) {
+    let height = if open { 14.0 } else { 10.0 };
+    let hand = Rect::from_center_size(pos, Vec2::new(12.0, height));
+
+    painter.rect(hand, Rounding::same(3.0), FILL, Stroke::new(1.0, OUTLINE));
+
+    for i in 1..4 {
+        let x = hand.left() + i as f32 * 3.0;
+        painter.line_segment(
+            [
+                Pos2::new(x, hand.top()),
+                Pos2::new(x, hand.top() + height * 0.4),
+            ],
+            Stroke::new(1.0, OUTLINE),
+        );
+    }
+}
+
+fn paint_magnifier(painter: &Painter, pos: Pos2, plus: bool) {
+    paint_line(painter, pos + Vec2::splat(4.0), pos + Vec2::splat(10.0));
+    painter.circle(pos, 6.0, FILL, Stroke::new(1.0, OUTLINE));
+
+    painter.line_segment(
+        [pos - Vec2::X * 3.0, pos + Vec2::X * 3.0],
+        Stroke::new(1.0, OUTLINE),
+    );
+    if plus {
+        painter.line_segment(
+            [pos - Vec2::Y * 3.0, pos + Vec2::Y * 3.0],
+            Stroke::new(1.0, OUTLINE),
+        );
+    }
+}
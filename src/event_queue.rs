@@ -0,0 +1,187 @@
+use egui::{Event, TouchPhase};
+
+/// counters for the input queue between paints, from event_queue_stats
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// events handed to the queue
+    pub pushed: u64,
+    /// pointer moves merged into the one before them
+    pub coalesced: u64,
+    /// pointer and touch moves thrown away because the queue was full
+    pub dropped: u64,
+    /// events handed to egui
+    pub drained: u64,
+    /// the most events the queue has held at once
+    pub high_water: usize,
+}
+
+/// the events waiting for the next paint. hosts that stop swapping buffers (loading screens, pauses)
+/// would otherwise pile up mouse moves forever
+#[derive(Clone, Debug)]
+pub(crate) struct EventQueue {
+    events: Vec<Event>,
+    capacity: usize,
+    stats: QueueStats,
+}
+
+impl EventQueue {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            events: Vec::new(),
+            capacity,
+            stats: QueueStats::default(),
+        }
+    }
+
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+    }
+
+    pub(crate) fn stats(&self) -> QueueStats {
+        self.stats
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub(crate) fn push(&mut self, event: Event) {
+        self.stats.pushed += 1;
+
+        // only the latest position matters between two other events
+        if let Event::PointerMoved(pos) = event {
+            if let Some(Event::PointerMoved(last)) = self.events.last_mut() {
+                *last = pos;
+                self.stats.coalesced += 1;
+                return;
+            }
+        }
+
+        if self.events.len() >= self.capacity {
+            if let Some(index) = self.events.iter().position(is_droppable) {
+                self.events.remove(index);
+                self.stats.dropped += 1;
+            } else if is_droppable(&event) {
+                self.stats.dropped += 1;
+                return;
+            }
+            // buttons, keys and text are never lost, the queue grows past capacity for them instead
+        }
+
+        self.events.push(event);
+        self.stats.high_water = self.stats.high_water.max(self.events.len());
+    }
+
+    pub(crate) fn drain(&mut self) -> Vec<Event> {
+        self.stats.drained += self.events.len() as u64;
+
+        std::mem::take(&mut self.events)
+    }
+}
+
+/// events that only describe where things are, which a later event of the same kind makes up for
+fn is_droppable(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::PointerMoved(_)
+            | Event::Touch {
+                phase: TouchPhase::Move,
+                ..
+            }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::{Key, Modifiers, PointerButton, Pos2, TouchDeviceId, TouchId};
+
+    fn moved(x: f32) -> Event {
+        Event::PointerMoved(Pos2::new(x, 0.0))
+    }
+
+    fn button(pressed: bool) -> Event {
+        Event::PointerButton {
+            pos: Pos2::ZERO,
+            button: PointerButton::Primary,
+            pressed,
+            modifiers: Modifiers::NONE,
+        }
+    }
+
+    fn key(pressed: bool) -> Event {
+        Event::Key {
+            key: Key::A,
+            physical_key: None,
+            pressed,
+            repeat: false,
+            modifiers: Modifiers::NONE,
+        }
+    }
+
+    #[test]
+    fn consecutive_moves_coalesce() {
+        let mut queue = EventQueue::new(16);
+
+        queue.push(moved(1.0));
+        queue.push(moved(2.0));
+        queue.push(button(true));
+        queue.push(moved(3.0));
+        queue.push(moved(4.0));
+
+        assert_eq!(queue.drain(), [moved(2.0), button(true), moved(4.0)]);
+        assert_eq!(queue.stats().coalesced, 2);
+        assert_eq!(queue.stats().drained, 3);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn full_queue_drops_oldest_move() {
+        let mut queue = EventQueue::new(3);
+
+        queue.push(moved(1.0));
+        queue.push(key(true));
+        queue.push(moved(2.0));
+        queue.push(key(false));
+
+        assert_eq!(queue.drain(), [key(true), moved(2.0), key(false)]);
+        assert_eq!(queue.stats().dropped, 1);
+        assert_eq!(queue.stats().high_water, 3);
+    }
+
+    #[test]
+    fn full_queue_drops_touch_moves() {
+        let mut queue = EventQueue::new(1);
+        let touch = |phase| Event::Touch {
+            device_id: TouchDeviceId(0),
+            id: TouchId(0),
+            phase,
+            pos: Pos2::ZERO,
+            force: None,
+        };
+
+        queue.push(touch(TouchPhase::Move));
+        queue.push(touch(TouchPhase::End));
+
+        assert_eq!(queue.drain(), [touch(TouchPhase::End)]);
+    }
+
+    #[test]
+    fn buttons_and_keys_are_never_dropped() {
+        let mut queue = EventQueue::new(2);
+
+        for pressed in [true, false, true, false] {
+            queue.push(button(pressed));
+            queue.push(key(pressed));
+        }
+
+        // past capacity with nothing droppable queued, the new move is the one that goes
+        queue.push(moved(1.0));
+
+        let events = queue.drain();
+        assert_eq!(events.len(), 8);
+        assert!(!events.contains(&moved(1.0)));
+        assert_eq!(queue.stats().dropped, 1);
+        assert_eq!(queue.stats().pushed, 9);
+    }
+}
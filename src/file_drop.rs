@@ -0,0 +1,272 @@
+use crate::{EguiState, EventDisposition};
+use egui::{DroppedFile, Event, HoveredFile, Pos2};
+use std::path::PathBuf;
+use windows::{
+    core::implement,
+    Win32::{
+        Foundation::{HWND, POINT, POINTL},
+        Graphics::Gdi::{ScreenToClient, WindowFromDC},
+        System::{
+            Com::{
+                CoGetApartmentType, IDataObject, APTTYPE, APTTYPEQUALIFIER, APTTYPE_MAINSTA,
+                APTTYPE_STA, DVASPECT_CONTENT, FORMATETC, TYMED_HGLOBAL,
+            },
+            Ole::{
+                IDropTarget, IDropTarget_Impl, OleInitialize, OleUninitialize, RegisterDragDrop,
+                ReleaseStgMedium, RevokeDragDrop, CF_HDROP, DROPEFFECT, DROPEFFECT_COPY,
+                DROPEFFECT_NONE,
+            },
+            SystemServices::MODIFIERKEYS_FLAGS,
+            Threading::GetCurrentThreadId,
+        },
+        UI::{
+            Shell::{DragAcceptFiles, DragFinish, DragQueryFileW, DragQueryPoint, HDROP},
+            WindowsAndMessaging::{
+                GetWindowLongPtrW, GetWindowThreadProcessId, GWL_EXSTYLE, WS_EX_ACCEPTFILES,
+            },
+        },
+    },
+};
+
+/// how drops reach the window, decided once by register
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum DropSource {
+    /// the host already took drops, WM_DROPFILES outside the overlay is still its own
+    Host,
+    /// WM_DROPFILES turned on by register, because ole isn't available on the window's thread
+    DropFiles,
+    /// our IDropTarget, which also reports files hovering over the overlay
+    Ole,
+}
+
+/// opts the window into drops; has to run on the window's thread, so on_event calls it the first time it runs.
+/// a host that already accepts files keeps getting its WM_DROPFILES for drops outside the overlay
+pub(crate) unsafe fn register(hwnd: HWND) -> DropSource {
+    if GetWindowLongPtrW(hwnd, GWL_EXSTYLE) as u32 & WS_EX_ACCEPTFILES.0 != 0 {
+        return DropSource::Host;
+    }
+
+    DragAcceptFiles(hwnd, true);
+
+    // setting up com on the host's thread would keep it from picking a multithreaded apartment later,
+    // so ole is only used where the host already chose single threaded
+    if !is_single_threaded() || OleInitialize(None).is_err() {
+        return DropSource::DropFiles;
+    }
+
+    let target: IDropTarget = DropTarget.into();
+    match RegisterDragDrop(hwnd, &target) {
+        Ok(()) => DropSource::Ole,
+        Err(_) => {
+            OleUninitialize();
+            DropSource::DropFiles
+        }
+    }
+}
+
+/// undoes register, a host that took drops itself keeps them; the OleInitialize can only be balanced from the window's thread
+pub(crate) unsafe fn revoke(hwnd: HWND, source: DropSource) {
+    if source == DropSource::Host {
+        return;
+    }
+
+    if source == DropSource::Ole {
+        let _ = RevokeDragDrop(hwnd);
+
+        if GetWindowThreadProcessId(hwnd, None) == GetCurrentThreadId() {
+            OleUninitialize();
+        }
+    }
+
+    DragAcceptFiles(hwnd, false);
+}
+
+unsafe fn is_single_threaded() -> bool {
+    let mut apartment = APTTYPE::default();
+    let mut qualifier = APTTYPEQUALIFIER::default();
+
+    CoGetApartmentType(&mut apartment, &mut qualifier).is_ok()
+        && (apartment == APTTYPE_STA || apartment == APTTYPE_MAINSTA)
+}
+
+/// WM_DROPFILES, only kept from a host that takes drops itself when the files land on egui
+pub(crate) unsafe fn handle_drop_files(state: &mut EguiState, wparam: usize) -> EventDisposition {
+    let hdrop = HDROP(wparam as isize);
+
+    let mut point = POINT::default();
+    DragQueryPoint(hdrop, &mut point);
+    let pos = Pos2::new(point.x as f32, point.y as f32) / crate::pixels_per_point(state);
+
+    if !state.visible || state.egui_ctx.layer_id_at(pos).is_none() {
+        if state.drop_source == Some(DropSource::Host) {
+            return EventDisposition::PassThrough;
+        }
+
+        // a host that never asked for drops wouldn't know to free them
+        DragFinish(hdrop);
+        return EventDisposition::Consume(0);
+    }
+
+    state.events.push(Event::PointerMoved(pos));
+    state
+        .dropped_files
+        .extend(query_files(hdrop).into_iter().map(|path| DroppedFile {
+            path: Some(path),
+            ..Default::default()
+        }));
+
+    DragFinish(hdrop);
+
+    EventDisposition::Consume(0)
+}
+
+fn query_files(hdrop: HDROP) -> Vec<PathBuf> {
+    unsafe {
+        let count = DragQueryFileW(hdrop, u32::MAX, None);
+
+        (0..count)
+            .filter_map(|index| {
+                let length = DragQueryFileW(hdrop, index, None) as usize;
+                let mut buffer = vec![0; length + 1];
+
+                if DragQueryFileW(hdrop, index, Some(&mut buffer)) == 0 {
+                    return None;
+                }
+
+                Some(PathBuf::from(String::from_utf16_lossy(&buffer[..length])))
+            })
+            .collect()
+    }
+}
+
+fn query_data_object(data: &IDataObject) -> Vec<PathBuf> {
+    let format = FORMATETC {
+        cfFormat: CF_HDROP.0,
+        ptd: std::ptr::null_mut(),
+        dwAspect: DVASPECT_CONTENT.0,
+        lindex: -1,
+        tymed: TYMED_HGLOBAL.0 as u32,
+    };
+
+    unsafe {
+        let Ok(mut medium) = data.GetData(&format) else {
+            return Vec::new();
+        };
+
+        let files = query_files(HDROP(medium.u.hGlobal.0 as isize));
+        ReleaseStgMedium(&mut medium);
+
+        files
+    }
+}
+
+/// the drag position in points, if it's over egui
+unsafe fn drag_pos(state: &EguiState, pt: &POINTL) -> Option<Pos2> {
+    let mut point = POINT { x: pt.x, y: pt.y };
+    let _ = ScreenToClient(WindowFromDC(state.window_handle), &mut point);
+    let pos = Pos2::new(point.x as f32, point.y as f32) / crate::pixels_per_point(state);
+
+    state.egui_ctx.layer_id_at(pos).map(|_| pos)
+}
+
+/// the ole side of file drops, called on the window's thread while a drag is over it
+#[implement(IDropTarget)]
+struct DropTarget;
+
+impl DropTarget {
+    /// tracks the drag as pointer movement, so egui can show what it's hovering over
+    unsafe fn update(&self, pt: &POINTL, effect: *mut DROPEFFECT) -> Option<Pos2> {
+        let state = crate::state().ok()?;
+        let pos = drag_pos(state, pt);
+
+        if let Some(pos) = pos {
+            state.pointer_pos = pos;
+            state.events.push(Event::PointerMoved(pos));
+        }
+
+        if !effect.is_null() {
+            *effect = match pos {
+                Some(_) if state.visible => DROPEFFECT_COPY,
+                _ => DROPEFFECT_NONE,
+            };
+        }
+
+        pos.filter(|_| state.visible)
+    }
+}
+
+#[allow(non_snake_case)]
+impl IDropTarget_Impl for DropTarget {
+    fn DragEnter(
+        &self,
+        data: Option<&IDataObject>,
+        _key_state: MODIFIERKEYS_FLAGS,
+        pt: &POINTL,
+        effect: *mut DROPEFFECT,
+    ) -> windows::core::Result<()> {
+        unsafe {
+            if let Ok(state) = crate::state() {
+                state.hovered_files = data
+                    .map(query_data_object)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|path| HoveredFile {
+                        path: Some(path),
+                        ..Default::default()
+                    })
+                    .collect();
+            }
+
+            self.update(pt, effect);
+        }
+
+        Ok(())
+    }
+
+    fn DragOver(
+        &self,
+        _key_state: MODIFIERKEYS_FLAGS,
+        pt: &POINTL,
+        effect: *mut DROPEFFECT,
+    ) -> windows::core::Result<()> {
+        unsafe { self.update(pt, effect) };
+
+        Ok(())
+    }
+
+    fn DragLeave(&self) -> windows::core::Result<()> {
+        if let Ok(state) = crate::state() {
+            state.hovered_files.clear();
+            state.events.push(Event::PointerGone);
+        }
+
+        Ok(())
+    }
+
+    fn Drop(
+        &self,
+        data: Option<&IDataObject>,
+        _key_state: MODIFIERKEYS_FLAGS,
+        pt: &POINTL,
+        effect: *mut DROPEFFECT,
+    ) -> windows::core::Result<()> {
+        unsafe {
+            let over_egui = self.update(pt, effect).is_some();
+
+            if let Ok(state) = crate::state() {
+                state.hovered_files.clear();
+
+                if let Some(data) = data.filter(|_| over_egui) {
+                    state
+                        .dropped_files
+                        .extend(query_data_object(data).into_iter().map(|path| DroppedFile {
+                            path: Some(path),
+                            ..Default::default()
+                        }));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,391 @@
+use egui::{Event, Key, Modifiers, PointerButton, Pos2, Rect, Vec2};
+
+/// the buttons of an xbox style controller
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadButton {
+    DpadUp,
+    DpadDown,
+    DpadLeft,
+    DpadRight,
+    A,
+    B,
+    X,
+    Y,
+    LeftShoulder,
+    RightShoulder,
+    Start,
+    Back,
+    LeftThumb,
+    RightThumb,
+}
+
+/// a snapshot of a controller, filled in from XInput, SDL or whatever the host uses
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GamepadState {
+    buttons: u16,
+    /// -1.0 to 1.0 on each axis, y pointing up like XInput
+    pub left_stick: Vec2,
+    /// -1.0 to 1.0 on each axis, y pointing up like XInput
+    pub right_stick: Vec2,
+}
+
+impl GamepadState {
+    pub fn set(&mut self, button: GamepadButton, pressed: bool) {
+        if pressed {
+            self.buttons |= 1 << button as u16;
+        } else {
+            self.buttons &= !(1 << button as u16);
+        }
+    }
+
+    pub fn is_pressed(&self, button: GamepadButton) -> bool {
+        self.buttons & 1 << button as u16 != 0
+    }
+}
+
+/// what a button does in the overlay
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GamepadAction {
+    /// moves keyboard focus to the closest widget in that direction, like the arrow keys
+    FocusUp,
+    FocusDown,
+    FocusLeft,
+    FocusRight,
+    /// like tab and shift+tab
+    FocusNext,
+    FocusPrevious,
+    /// clicks the focused widget, like enter
+    Activate,
+    /// drops focus and closes popups, like escape
+    Cancel,
+    /// clicks with the primary button at the stick cursor
+    Click,
+    /// clicks with the secondary button at the stick cursor
+    SecondaryClick,
+}
+
+/// which stick does what, and which button triggers which action
+#[derive(Clone, Debug, PartialEq)]
+pub struct GamepadMapping {
+    pub buttons: Vec<(GamepadButton, GamepadAction)>,
+    /// stick deflection below this is ignored
+    pub dead_zone: f32,
+    /// moves the pointer, None leaves the pointer alone
+    pub cursor_stick: Option<GamepadStick>,
+    /// points per second at full deflection
+    pub cursor_speed: f32,
+    /// scrolls whatever is under the pointer, None disables stick scrolling
+    pub scroll_stick: Option<GamepadStick>,
+    /// points per second at full deflection
+    pub scroll_speed: f32,
+    /// seconds a focus direction has to be held before it repeats
+    pub repeat_delay: f64,
+    /// seconds between repeats after that
+    pub repeat_interval: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GamepadStick {
+    Left,
+    Right,
+}
+
+impl Default for GamepadMapping {
+    fn default() -> Self {
+        Self {
+            buttons: vec![
+                (GamepadButton::DpadUp, GamepadAction::FocusUp),
+                (GamepadButton::DpadDown, GamepadAction::FocusDown),
+                (GamepadButton::DpadLeft, GamepadAction::FocusLeft),
+                (GamepadButton::DpadRight, GamepadAction::FocusRight),
+                (GamepadButton::RightShoulder, GamepadAction::FocusNext),
+                (GamepadButton::LeftShoulder, GamepadAction::FocusPrevious),
+                (GamepadButton::A, GamepadAction::Activate),
+                (GamepadButton::B, GamepadAction::Cancel),
+                (GamepadButton::X, GamepadAction::Click),
+                (GamepadButton::Y, GamepadAction::SecondaryClick),
+            ],
+            dead_zone: 0.2,
+            cursor_stick: Some(GamepadStick::Left),
+            cursor_speed: 800.0,
+            scroll_stick: Some(GamepadStick::Right),
+            scroll_speed: 1200.0,
+            repeat_delay: 0.4,
+            repeat_interval: 0.1,
+        }
+    }
+}
+
+/// turns controller snapshots into egui events. doesn't touch windows or XInput,
+/// so it runs the same anywhere given states and timestamps
+#[derive(Clone, Debug, Default)]
+pub struct GamepadNavigator {
+    previous: GamepadState,
+    /// when each held button next repeats
+    repeat_at: Vec<(GamepadButton, f64)>,
+    cursor: Option<Pos2>,
+    last_time: Option<f64>,
+}
+
+impl GamepadNavigator {
+    /// where the stick cursor is, once the stick has moved it
+    pub fn cursor(&self) -> Option<Pos2> {
+        self.cursor
+    }
+
+    /// events for this frame's state. time is in seconds, screen_rect keeps the cursor on screen
+    pub fn update(
+        &mut self,
+        mapping: &GamepadMapping,
+        gamepad: &GamepadState,
+        time: f64,
+        screen_rect: Rect,
+    ) -> Vec<Event> {
+        let dt = self
+            .last_time
+            .map_or(0.0, |last| (time - last).clamp(0.0, 0.1)) as f32;
+        self.last_time = Some(time);
+
+        let mut events = Vec::new();
+
+        if let Some(stick) = mapping.cursor_stick {
+            let motion = apply_dead_zone(stick_of(gamepad, stick), mapping.dead_zone);
+
+            if motion != Vec2::ZERO {
+                let cursor = self.cursor.unwrap_or_else(|| screen_rect.center());
+                let cursor = screen_rect.clamp(cursor + flip(motion) * mapping.cursor_speed * dt);
+
+                self.cursor = Some(cursor);
+                events.push(Event::PointerMoved(cursor));
+            }
+        }
+
+        if let Some(stick) = mapping.scroll_stick {
+            let motion = apply_dead_zone(stick_of(gamepad, stick), mapping.dead_zone);
+
+            if motion != Vec2::ZERO {
+                // pushing the stick down shows what's further down, so content moves up
+                events.push(Event::Scroll(-flip(motion) * mapping.scroll_speed * dt));
+            }
+        }
+
+        for &(button, action) in &mapping.buttons {
+            let pressed = gamepad.is_pressed(button);
+            let was_pressed = self.previous.is_pressed(button);
+
+            if pressed && !was_pressed {
+                self.repeat_at.retain(|(b, _)| *b != button);
+                if is_repeating(action) {
+                    self.repeat_at.push((button, time + mapping.repeat_delay));
+                }
+
+                self.push_action(&mut events, action, true, false);
+            } else if !pressed && was_pressed {
+                self.repeat_at.retain(|(b, _)| *b != button);
+                self.push_action(&mut events, action, false, false);
+            } else if pressed {
+                if let Some((_, at)) = self.repeat_at.iter_mut().find(|(b, _)| *b == button) {
+                    if time >= *at {
+                        *at = time + mapping.repeat_interval;
+                        self.push_action(&mut events, action, true, true);
+                    }
+                }
+            }
+        }
+
+        self.previous = *gamepad;
+
+        events
+    }
+
+    fn push_action(
+        &self,
+        events: &mut Vec<Event>,
+        action: GamepadAction,
+        pressed: bool,
+        repeat: bool,
+    ) {
+        let (key, modifiers) = match action {
+            GamepadAction::FocusUp => (Key::ArrowUp, Modifiers::NONE),
+            GamepadAction::FocusDown => (Key::ArrowDown, Modifiers::NONE),
+            GamepadAction::FocusLeft => (Key::ArrowLeft, Modifiers::NONE),
+            GamepadAction::FocusRight => (Key::ArrowRight, Modifiers::NONE),
+            GamepadAction::FocusNext => (Key::Tab, Modifiers::NONE),
+            GamepadAction::FocusPrevious => (Key::Tab, Modifiers::SHIFT),
+            GamepadAction::Activate => (Key::Enter, Modifiers::NONE),
+            GamepadAction::Cancel => (Key::Escape, Modifiers::NONE),
+            GamepadAction::Click | GamepadAction::SecondaryClick => {
+                // without a stick cursor there is nothing to click at
+                if let Some(pos) = self.cursor {
+                    let button = match action {
+                        GamepadAction::Click => PointerButton::Primary,
+                        _ => PointerButton::Secondary,
+                    };
+
+                    events.push(Event::PointerButton {
+                        pos,
+                        button,
+                        pressed,
+                        modifiers: Modifiers::NONE,
+                    });
+                }
+
+                return;
+            }
+        };
+
+        events.push(Event::Key {
+            key,
+            physical_key: None,
+            pressed,
+            repeat,
+            modifiers,
+        });
+    }
+}
+
+fn is_repeating(action: GamepadAction) -> bool {
+    matches!(
+        action,
+        GamepadAction::FocusUp
+            | GamepadAction::FocusDown
+            | GamepadAction::FocusLeft
+            | GamepadAction::FocusRight
+            | GamepadAction::FocusNext
+            | GamepadAction::FocusPrevious
+    )
+}
+
+fn stick_of(gamepad: &GamepadState, stick: GamepadStick) -> Vec2 {
+    match stick {
+        GamepadStick::Left => gamepad.left_stick,
+        GamepadStick::Right => gamepad.right_stick,
+    }
+}
+
+/// rescales so motion starts at zero right outside the dead zone instead of jumping
+fn apply_dead_zone(stick: Vec2, dead_zone: f32) -> Vec2 {
+    let length = stick.length().min(1.0);
+
+    if length <= dead_zone {
+        return Vec2::ZERO;
+    }
+
+    stick.normalized() * (length - dead_zone) / (1.0 - dead_zone)
+}
+
+/// stick y points up, screen y points down
+fn flip(stick: Vec2) -> Vec2 {
+    Vec2::new(stick.x, -stick.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen() -> Rect {
+        Rect::from_min_size(Pos2::ZERO, Vec2::new(800.0, 600.0))
+    }
+
+    fn keys(events: &[Event]) -> Vec<(Key, bool, bool)> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Key {
+                    key,
+                    pressed,
+                    repeat,
+                    ..
+                } => Some((*key, *pressed, *repeat)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn buttons_fire_on_edges() {
+        let mapping = GamepadMapping::default();
+        let mut navigator = GamepadNavigator::default();
+        let mut gamepad = GamepadState::default();
+
+        gamepad.set(GamepadButton::A, true);
+        let events = navigator.update(&mapping, &gamepad, 0.0, screen());
+        assert_eq!(keys(&events), [(Key::Enter, true, false)]);
+
+        // activate doesn't repeat, however long it's held
+        let events = navigator.update(&mapping, &gamepad, 5.0, screen());
+        assert!(events.is_empty());
+
+        gamepad.set(GamepadButton::A, false);
+        let events = navigator.update(&mapping, &gamepad, 5.1, screen());
+        assert_eq!(keys(&events), [(Key::Enter, false, false)]);
+    }
+
+    #[test]
+    fn focus_repeats_after_delay() {
+        let mapping = GamepadMapping::default();
+        let mut navigator = GamepadNavigator::default();
+        let mut gamepad = GamepadState::default();
+
+        gamepad.set(GamepadButton::DpadDown, true);
+        let events = navigator.update(&mapping, &gamepad, 0.0, screen());
+        assert_eq!(keys(&events), [(Key::ArrowDown, true, false)]);
+
+        assert!(navigator
+            .update(&mapping, &gamepad, 0.3, screen())
+            .is_empty());
+
+        let events = navigator.update(&mapping, &gamepad, 0.4, screen());
+        assert_eq!(keys(&events), [(Key::ArrowDown, true, true)]);
+
+        assert!(navigator
+            .update(&mapping, &gamepad, 0.45, screen())
+            .is_empty());
+
+        let events = navigator.update(&mapping, &gamepad, 0.5, screen());
+        assert_eq!(keys(&events), [(Key::ArrowDown, true, true)]);
+
+        // a new press starts the delay over
+        gamepad.set(GamepadButton::DpadDown, false);
+        navigator.update(&mapping, &gamepad, 0.55, screen());
+        gamepad.set(GamepadButton::DpadDown, true);
+        navigator.update(&mapping, &gamepad, 0.6, screen());
+        assert!(navigator
+            .update(&mapping, &gamepad, 0.9, screen())
+            .is_empty());
+    }
+
+    #[test]
+    fn sticks_ignore_dead_zone() {
+        let mapping = GamepadMapping::default();
+        let mut navigator = GamepadNavigator::default();
+        let mut gamepad = GamepadState {
+            left_stick: Vec2::new(0.15, 0.0),
+            right_stick: Vec2::new(0.0, -0.1),
+            ..Default::default()
+        };
+
+        assert!(navigator
+            .update(&mapping, &gamepad, 0.0, screen())
+            .is_empty());
+        assert!(navigator
+            .update(&mapping, &gamepad, 0.1, screen())
+            .is_empty());
+        assert_eq!(navigator.cursor(), None);
+
+        // full deflection for 0.1s moves cursor_speed / 10 points right of the center
+        gamepad.left_stick = Vec2::new(1.0, 0.0);
+        navigator.update(&mapping, &gamepad, 0.2, screen());
+        assert_eq!(navigator.cursor(), Some(Pos2::new(480.0, 300.0)));
+    }
+
+    #[test]
+    fn dead_zone_has_no_jump() {
+        assert_eq!(apply_dead_zone(Vec2::new(0.2, 0.0), 0.2), Vec2::ZERO);
+        assert!(apply_dead_zone(Vec2::new(0.21, 0.0), 0.2).x < 0.02);
+        assert_eq!(
+            apply_dead_zone(Vec2::new(0.0, 1.0), 0.2),
+            Vec2::new(0.0, 1.0)
+        );
+    }
+}
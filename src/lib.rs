@@ -1,37 +1,218 @@
-use clipboard::{windows_clipboard::WindowsClipboardContext, ClipboardProvider};
-use egui::{Event, Key, Modifiers, PointerButton, Pos2, RawInput, Rect, Vec2};
-use std::sync::Arc;
-use windows::{
-    Wdk::System::SystemInformation::NtQuerySystemTime,
-    Win32::{
-        Foundation::RECT,
-        Graphics::{
-            Gdi::{WindowFromDC, HDC},
-            OpenGL::{
-                wglCreateContext, wglDeleteContext, wglGetCurrentContext, wglGetProcAddress,
-                wglMakeCurrent, HGLRC,
-            },
+use egui::{
+    CursorIcon, Event, Id, Key, LayerId, Modifiers, MouseWheelUnit, Order, PointerButton, Pos2,
+    RawInput, Rect, TouchDeviceId, TouchId, TouchPhase, Vec2, ViewportId, ViewportInfo,
+};
+use std::{
+    collections::VecDeque,
+    ptr::{addr_of, addr_of_mut},
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use windows::Win32::{
+    Foundation::{HWND, LPARAM, LRESULT, POINT, RECT, WPARAM},
+    Graphics::{
+        Gdi::{
+            ClientToScreen, GetMonitorInfoW, MonitorFromWindow, ScreenToClient, WindowFromDC, HDC,
+            MONITORINFO, MONITOR_DEFAULTTONEAREST,
         },
-        System::{
-            LibraryLoader::{GetModuleHandleA, GetProcAddress},
-            SystemServices::{MK_CONTROL, MK_SHIFT},
+        OpenGL::{
+            wglCreateContext, wglDeleteContext, wglGetCurrentContext, wglGetProcAddress,
+            wglMakeCurrent, HGLRC,
         },
-        UI::{Input::KeyboardAndMouse::*, WindowsAndMessaging::*},
+    },
+    System::{
+        LibraryLoader::{GetModuleHandleA, GetProcAddress},
+        SystemServices::{MK_CONTROL, MK_SHIFT},
+    },
+    UI::{
+        HiDpi::GetDpiForWindow,
+        Input::{KeyboardAndMouse::*, Pointer::*, *},
+        WindowsAndMessaging::*,
     },
 };
 
+mod clipboard;
+mod clock;
+mod cursor;
+mod event_queue;
+mod file_drop;
+mod gamepad;
+mod routing;
+mod scheduler;
+mod ui_thread;
+mod url;
+
+#[cfg(windows)]
+pub use self::clipboard::SystemClipboard;
+pub use self::clipboard::{with_clipboard, Clipboard, MemoryClipboard};
+pub use clock::{Clock, ManualClock, PerformanceClock};
+pub use event_queue::QueueStats;
+pub use gamepad::{
+    GamepadAction, GamepadButton, GamepadMapping, GamepadNavigator, GamepadState, GamepadStick,
+};
+pub use routing::{EventDisposition, InputMode, InputPolicy, MessageClass, RoutedMessage};
+pub use scheduler::{RateLimit, RenderMode};
+pub use url::{open_in_browser, UrlHandler, UrlPolicy};
+
+/// lives in Win32_UI_Controls, which is a lot to pull in for one constant
+const WM_MOUSELEAVE: u32 = 0x02A3;
+
 struct EguiState {
     egui_ctx: egui::Context,
     painter: egui_glow::Painter,
-    events: Vec<egui::Event>,
+    events: event_queue::EventQueue,
     modifiers: Option<Modifiers>,
     window_handle: HDC,
     original_gl_context: HGLRC,
     new_gl_context: HGLRC,
+    options: Options,
+    host_translates: bool,
+    synthesized_text: VecDeque<char>,
+    dead_key: Option<DeadKey>,
+    virtual_cursor: Option<Pos2>,
+    cursor_icon: CursorIcon,
+    tracking_mouse: bool,
+    focused: bool,
+    pressed_keys: Vec<Key>,
+    pointer_pos: Pos2,
+    has_capture: bool,
+    /// SetCapture (true) or ReleaseCapture (false) waiting for run_deferred
+    capture_request: Option<bool>,
+    captured_buttons: Vec<PointerButton>,
+    host_pressed: Vec<(u32, usize)>,
+    visible: bool,
+    clipboard: clipboard::SharedClipboard,
+    clock: Box<dyn Clock>,
+    frame_timer: clock::FrameTimer,
+    url_handler: UrlHandler,
+    /// None until on_event got to register for drops
+    drop_source: Option<file_drop::DropSource>,
+    /// file_drop::register waiting for run_deferred
+    register_drops: bool,
+    hovered_files: Vec<egui::HoveredFile>,
+    dropped_files: Vec<egui::DroppedFile>,
+    /// None until on_event first runs, windows is asked every frame until then
+    window: Option<WindowMetrics>,
+    scheduler: scheduler::Scheduler,
+    /// Some between start_ui_thread and stop_ui_thread
+    ui_thread: Option<ui_thread::UiThread>,
+    /// texture changes from frames that weren't drawn, uploaded with the next one that is
+    pending_textures: egui::TexturesDelta,
+    /// GL_MAX_TEXTURE_SIZE, queried once by the painter
+    max_texture_side: usize,
+    /// the monitor's dpi over 96
+    dpi_scale: f32,
+    gamepad: GamepadNavigator,
+    visibility_callback: Option<Rc<dyn Fn(bool)>>,
+    /// the visibility to tell visibility_callback about in run_deferred
+    visibility_changed: Option<bool>,
+    /// the host window's title, read once by init and again after each WM_SETTEXT
+    title: String,
+    /// a WM_SETTEXT went by, the next on_event reads the title again
+    title_changed: bool,
+}
+
+/// settings for how input is handled, can be changed at any time with set_options
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// generates text from WM_KEYDOWN with ToUnicodeEx instead of waiting for WM_CHAR;
+    /// needed for hosts that never call TranslateMessage. stops by itself once a WM_CHAR shows up
+    pub synthesize_text: bool,
+    /// turns WM_POINTER* from touch screens and pens into touch events (for pinch zoom and such),
+    /// and ignores the mouse messages windows makes up from them
+    pub pointer_input: bool,
+    /// drives a virtual cursor from WM_INPUT mouse motion instead of WM_MOUSE* positions, for games
+    /// that hide the cursor and keep recentering it. the host has to have registered for raw mouse input
+    pub raw_mouse: bool,
+    /// has paint draw the cursor itself, for hosts that hide the real one
+    pub software_cursor: SoftwareCursor,
+    /// points scrolled per wheel notch
+    pub scroll_speed: f32,
+    /// zoom factor per wheel notch while holding ctrl, partial notches zoom proportionally
+    pub zoom_step: f32,
+    /// which input messages are kept from the host
+    pub input_policy: InputPolicy,
+    /// shows and hides the overlay, for example Key::Insert or Key::F12. never reaches the host
+    pub toggle_key: Option<Key>,
+    /// scales the whole overlay on top of the monitor's dpi scaling; egui's zoom factor (ctrl plus and minus,
+    /// Context::set_zoom_factor) goes on top of both
+    pub ui_scale: f32,
+    /// what gamepad_input does with each button and stick
+    pub gamepad: GamepadMapping,
+    /// which hyperlinks clicked in the overlay get opened
+    pub url_policy: UrlPolicy,
+    /// lets files be dropped onto overlay panels. makes the window accept drops if it didn't already,
+    /// until destroy
+    pub file_drop: bool,
+    /// how many events wait for the next paint before pointer moves start getting dropped;
+    /// keys, buttons and text are always kept
+    pub event_capacity: usize,
+    /// whether paint runs the ui every frame or only when something changed
+    pub render_mode: RenderMode,
+    /// caps how often the ui runs no matter how fast the host paints
+    pub rate_limit: RateLimit,
+}
+
+/// when paint should draw its own cursor
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SoftwareCursor {
+    #[default]
+    Never,
+    Always,
+    /// only while the host has hidden the os cursor with ShowCursor
+    WhenHidden,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            synthesize_text: false,
+            pointer_input: true,
+            raw_mouse: false,
+            software_cursor: SoftwareCursor::Never,
+            scroll_speed: 10.0,
+            zoom_step: 1.1,
+            input_policy: InputPolicy::default(),
+            toggle_key: None,
+            ui_scale: 1.0,
+            gamepad: GamepadMapping::default(),
+            url_policy: UrlPolicy::default(),
+            file_drop: false,
+            event_capacity: 1024,
+            render_mode: RenderMode::default(),
+            rate_limit: RateLimit::default(),
+        }
+    }
+}
+
+/// the host window as of its last WM_SIZE and WM_MOVE, so paint doesn't have to ask every frame
+#[derive(Clone, Copy, Debug, Default)]
+struct WindowMetrics {
+    /// client area in pixels
+    client_size: (u32, u32),
+    /// top left of the client area in screen pixels
+    client_origin: Option<POINT>,
+    outer_rect: Option<RECT>,
+    monitor_rect: Option<RECT>,
+    minimized: bool,
+    maximized: bool,
+}
+
+/// a dead key (like ` or ^ on some layouts) waiting to be combined with the next key
+struct DeadKey {
+    virtual_key: u32,
+    scan_code: u32,
+    keyboard_state: [u8; 256],
 }
 
 static mut STATE: Option<EguiState> = None; // unsafe, sure, but also way easier to make work
 
+/// the state, or NotInit before init; everything reaches STATE through here
+fn state() -> Result<&'static mut EguiState, Error> {
+    unsafe { (*addr_of_mut!(STATE)).as_mut() }.ok_or(Error::NotInit)
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("state was not initialized")]
@@ -54,18 +235,22 @@ pub enum Error {
     #[error("failed to get window size")]
     WindowSize,
 
+    #[error("ui thread is already running")]
+    UiThreadRunning,
+    #[error("ui thread is not running")]
+    UiThreadNotRunning,
+    #[error("ui thread panicked")]
+    UiThreadPanicked,
+
     #[error("could not create painter: `{0}`")]
     PainterError(#[from] egui_glow::PainterError),
 }
 
 /// should be called when exiting to remove gl objects and such
 pub fn destroy() -> Result<(), Error> {
-    let state = unsafe {
-        match &mut STATE {
-            Some(s) => s,
-            None => return Err(Error::NotInit),
-        }
-    };
+    let state = state()?;
+
+    state.ui_thread = None;
 
     unsafe {
         let _ = wglDeleteContext(state.new_gl_context);
@@ -73,18 +258,30 @@ pub fn destroy() -> Result<(), Error> {
 
     state.painter.destroy();
 
+    // last, since it sends messages back through on_event. drop_source stays set so they don't register again
+    if let Some(drop_source) = state.drop_source {
+        unsafe { file_drop::revoke(WindowFromDC(state.window_handle), drop_source) };
+    }
+
     Ok(())
 }
 
 /// checks if initialized
 pub fn is_init() -> bool {
-    unsafe { &STATE }.is_some()
+    unsafe { (*addr_of!(STATE)).is_some() }
 }
 
 /// initializes state; needed to be called before paint, on_event, get_window_rect, and destroy
 ///
 /// # Safety
 pub unsafe fn init(window_handle: HDC) -> Result<(), Error> {
+    init_with_options(window_handle, Options::default())
+}
+
+/// same as init, but with custom options
+///
+/// # Safety
+pub unsafe fn init_with_options(window_handle: HDC, options: Options) -> Result<(), Error> {
     if is_init() {
         return Err(Error::AlreadyInit);
     };
@@ -123,7 +320,10 @@ pub unsafe fn init(window_handle: HDC) -> Result<(), Error> {
         Err(err) => return Err(err.into()),
     };
 
+    let max_texture_side = painter.max_texture_side();
     let egui_ctx = egui::Context::default();
+    let scheduler = scheduler::Scheduler::new(&egui_ctx);
+    let clipboard = clipboard::share(&egui_ctx, clipboard::default_clipboard());
 
     if wglMakeCurrent(window_handle, original_gl_context).is_err() {
         return Err(Error::CtxSwitch);
@@ -132,59 +332,417 @@ pub unsafe fn init(window_handle: HDC) -> Result<(), Error> {
     STATE = Some(EguiState {
         egui_ctx,
         painter,
-        events: Vec::new(),
+        events: event_queue::EventQueue::new(options.event_capacity),
         modifiers: None,
         window_handle,
         original_gl_context,
         new_gl_context,
+        options,
+        host_translates: false,
+        synthesized_text: VecDeque::new(),
+        dead_key: None,
+        virtual_cursor: None,
+        cursor_icon: CursorIcon::Default,
+        tracking_mouse: false,
+        focused: GetForegroundWindow() == WindowFromDC(window_handle),
+        pressed_keys: Vec::new(),
+        pointer_pos: Pos2::ZERO,
+        has_capture: false,
+        capture_request: None,
+        captured_buttons: Vec::new(),
+        host_pressed: Vec::new(),
+        visible: true,
+        clipboard,
+        clock: Box::new(PerformanceClock::new()),
+        frame_timer: clock::FrameTimer::default(),
+        url_handler: Arc::new(open_in_browser),
+        drop_source: None,
+        register_drops: false,
+        hovered_files: Vec::new(),
+        dropped_files: Vec::new(),
+        window: None,
+        scheduler,
+        ui_thread: None,
+        pending_textures: egui::TexturesDelta::default(),
+        max_texture_side,
+        dpi_scale: get_dpi_scale(window_handle),
+        gamepad: GamepadNavigator::default(),
+        visibility_callback: None,
+        visibility_changed: None,
+        title: get_window_title(window_handle),
+        title_changed: false,
     });
 
     Ok(())
 }
 
+/// returns a copy of the current options
+pub fn get_options() -> Result<Options, Error> {
+    let state = state()?;
+
+    Ok(state.options.clone())
+}
+
+/// replaces the current options
+pub fn set_options(options: Options) -> Result<(), Error> {
+    let state = state()?;
+
+    state.events.set_capacity(options.event_capacity);
+    state.scheduler.invalidate();
+    // a host already found to translate keeps doing so, starting over would double its text
+    if options.synthesize_text != state.options.synthesize_text {
+        state.host_translates = false;
+        state.synthesized_text.clear();
+        state.dead_key = None;
+    }
+
+    state.options = options;
+    state.virtual_cursor = None;
+
+    Ok(())
+}
+
+/// shows or hides the overlay; while hidden paint does nothing and on_event lets all input through
+pub fn set_visible(visible: bool) -> Result<(), Error> {
+    let state = state()?;
+
+    apply_visibility(state, visible);
+    run_deferred();
+
+    Ok(())
+}
+
+/// checks if the overlay is shown
+pub fn is_visible() -> Result<bool, Error> {
+    let state = state()?;
+
+    Ok(state.visible)
+}
+
+/// called with the new visibility whenever the overlay is shown or hidden, for example to pause the game
+/// or give the mouse back to it. runs on whatever thread caused the change (the wndproc for the toggle key),
+/// after the change is done, so it's free to call back into this crate
+pub fn on_visibility_change(callback: Box<dyn Fn(bool)>) -> Result<(), Error> {
+    let state = state()?;
+
+    state.visibility_callback = Some(callback.into());
+
+    Ok(())
+}
+
+/// replaces where copy, cut and paste go, for example with a MemoryClipboard to keep the overlay away from the
+/// system clipboard. egui only copies and pastes text, for images use with_clipboard from the ui
+pub fn set_clipboard(clipboard: Box<dyn Clipboard + Send>) -> Result<(), Error> {
+    let state = state()?;
+
+    // swapped in place, the ui's handle to it stays valid
+    if let Ok(mut current) = state.clipboard.lock() {
+        *current = clipboard;
+    }
+
+    Ok(())
+}
+
+/// replaces where egui's time comes from, for example with a ManualClock to test animations frame by frame
+pub fn set_clock(clock: Box<dyn Clock>) -> Result<(), Error> {
+    let state = state()?;
+
+    state.clock = clock;
+    // an interval across two clocks means nothing
+    state.frame_timer = clock::FrameTimer::default();
+
+    Ok(())
+}
+
+/// replaces how hyperlinks are opened, for example to show them in an in-game browser.
+/// url_policy still decides which ones get here, and the handler runs on its own thread
+pub fn set_url_handler(handler: UrlHandler) -> Result<(), Error> {
+    let state = state()?;
+
+    state.url_handler = handler;
+
+    Ok(())
+}
+
+/// counters for the events queued between paints, to spot a host that stopped painting
+pub fn event_queue_stats() -> Result<QueueStats, Error> {
+    let state = state()?;
+
+    Ok(state.events.stats())
+}
+
+/// milliseconds the ui function and tessellation take per run, smoothed; what RateLimit::cpu_budget_ms is compared to
+pub fn ui_cost_ms() -> Result<f32, Error> {
+    let state = state()?;
+
+    Ok(state.scheduler.run_cost_ms())
+}
+
+/// queues an event for the next paint, for hosts that read input through DirectInput, SDL or their own loop
+/// instead of a wndproc. key presses pushed here are released again on focus loss like the ones from on_event
+pub fn push_event(event: Event) -> Result<(), Error> {
+    let state = state()?;
+
+    match &event {
+        Event::Key {
+            key, pressed: true, ..
+        } if !state.pressed_keys.contains(key) => state.pressed_keys.push(*key),
+        Event::Key {
+            key,
+            pressed: false,
+            ..
+        } => state.pressed_keys.retain(|pressed| pressed != key),
+        Event::PointerMoved(pos) => state.pointer_pos = *pos,
+        _ => {}
+    }
+
+    state.events.push(event);
+
+    Ok(())
+}
+
+/// sets the modifiers reported with the next frame and with wheel and button events from on_event
+pub fn set_modifiers(modifiers: Modifiers) -> Result<(), Error> {
+    let state = state()?;
+
+    state.modifiers = Some(modifiers);
+
+    Ok(())
+}
+
+/// moves the egui pointer to pos, in points from the top left of the client area
+pub fn set_pointer_pos(pos: Pos2) -> Result<(), Error> {
+    let state = state()?;
+
+    state.pointer_pos = pos;
+    state.events.push(Event::PointerMoved(pos));
+
+    Ok(())
+}
+
+/// marks the window as focused or not; losing focus releases every held key
+pub fn set_focus(focused: bool) -> Result<(), Error> {
+    let state = state()?;
+
+    set_focused(state, focused);
+
+    Ok(())
+}
+
+/// feeds this frame's controller state into the overlay, call it once per frame for controller-only players.
+/// buttons move focus and click, sticks move a drawn cursor and scroll, see GamepadMapping
+pub fn gamepad_input(gamepad: &GamepadState) -> Result<(), Error> {
+    let state = state()?;
+
+    if !state.visible {
+        return Ok(());
+    }
+
+    let events = state.gamepad.update(
+        &state.options.gamepad,
+        gamepad,
+        state.clock.now(),
+        screen_rect(state)?,
+    );
+
+    for event in events {
+        if let Event::PointerMoved(pos) = event {
+            state.pointer_pos = pos;
+        }
+
+        state.events.push(event);
+    }
+
+    Ok(())
+}
+
 /// runs ui function and makes opengl calls to render to specified window
 ///
+/// with RenderMode::Retained the ui function only runs when something changed, and the last frame is drawn again otherwise
+///
 /// # Safety
 pub unsafe fn paint(hdc: HDC, run_fn: Box<dyn Fn(&egui::Context)>) -> Result<(), Error> {
-    let state = unsafe {
-        match &mut STATE {
-            Some(s) => s,
-            None => return Err(Error::NotInit),
+    let state = state()?;
+
+    // two threads running frames on one context would split their output between them
+    if state.ui_thread.is_some() {
+        return Err(Error::UiThreadRunning);
+    }
+
+    if !state.visible {
+        return Ok(());
+    }
+
+    switch_window(state, hdc);
+
+    let dimensions = screen_size(state)?;
+    let drawable = is_drawable(state, dimensions);
+
+    let now = state.clock.now();
+    let has_input = !state.events.is_empty() || !state.hovered_files.is_empty();
+    if state.scheduler.should_run(
+        state.options.render_mode,
+        state.options.rate_limit,
+        now,
+        has_input,
+        drawable.then_some(dimensions),
+    ) {
+        state.scheduler.started();
+
+        let started = Instant::now();
+        run_ui(state, run_fn, now, dimensions, drawable)?;
+        state.scheduler.record_cost(started.elapsed());
+    }
+
+    // the ui may still have run above so egui's time and input move on, but there is nothing to draw into
+    if !drawable {
+        return Ok(());
+    }
+
+    draw(state, dimensions)
+}
+
+/// moves egui's run and tessellation to a thread of its own, so the host's frame only pays for drawing.
+/// paint_async then takes the place of paint, and paint fails until stop_ui_thread
+///
+/// the thread has no gl context of its own: egui never needs one to run or tessellate, and texture uploads stay
+/// on the render thread next to the painter that owns the textures. input keeps coming from on_event and the
+/// other input functions on the host's threads. if the ui function panics, the next paint_async says so and
+/// paint can take over again
+pub fn start_ui_thread(run_fn: Box<dyn FnMut(&egui::Context) + Send>) -> Result<(), Error> {
+    let state = state()?;
+
+    if state.ui_thread.is_some() {
+        return Err(Error::UiThreadRunning);
+    }
+
+    state.ui_thread = Some(ui_thread::UiThread::spawn(state.egui_ctx.clone(), run_fn));
+
+    Ok(())
+}
+
+/// waits for the ui thread to finish its current run and stops it, paint works again afterwards
+pub fn stop_ui_thread() -> Result<(), Error> {
+    let state = state()?;
+
+    match state.ui_thread.take() {
+        Some(_) => Ok(()),
+        None => Err(Error::UiThreadNotRunning),
+    }
+}
+
+/// paint for when start_ui_thread is running: uploads and draws the newest frame the ui thread finished,
+/// and hands it this frame's input. never waits for the ui thread
+///
+/// # Safety
+pub unsafe fn paint_async(hdc: HDC) -> Result<(), Error> {
+    let state = state()?;
+
+    match &state.ui_thread {
+        Some(ui_thread) if ui_thread.is_running() => {}
+        Some(_) => {
+            // it only ends on its own by panicking, and nothing would ever answer the requests
+            state.ui_thread = None;
+            return Err(Error::UiThreadPanicked);
         }
-    };
+        None => return Err(Error::UiThreadNotRunning),
+    }
+
+    if !state.visible {
+        return Ok(());
+    }
+
+    switch_window(state, hdc);
+
+    let dimensions = screen_size(state)?;
+    let drawable = is_drawable(state, dimensions);
+
+    if let Some(frame) = state.ui_thread.as_ref().and_then(|t| t.take_frame()) {
+        handle_platform_output(state, frame.platform_output);
+        state.pending_textures.append(frame.textures_delta);
+
+        state.scheduler.ran(frame.now, frame.repaint_delay);
+        state.scheduler.record_cost(frame.cost);
+        state
+            .scheduler
+            .store(frame.primitives, frame.pixels_per_point, frame.dimensions);
+    }
+
+    let now = state.clock.now();
+    let has_input = !state.events.is_empty() || !state.hovered_files.is_empty();
+    if state.scheduler.should_run(
+        state.options.render_mode,
+        state.options.rate_limit,
+        now,
+        has_input,
+        drawable.then_some(dimensions),
+    ) {
+        state.scheduler.started();
+
+        let request = ui_thread::Request {
+            raw_input: get_raw_input(state)?,
+            draw_cursor: should_draw_cursor(state),
+            cursor_icon: state.cursor_icon,
+            now,
+            dimensions,
+        };
+
+        if let Some(ui_thread) = &state.ui_thread {
+            ui_thread.post(request);
+        }
+    }
+
+    if !drawable {
+        return Ok(());
+    }
+
+    draw(state, dimensions)
+}
 
+/// picks up the gl context to restore when the host paints into a different dc
+unsafe fn switch_window(state: &mut EguiState, hdc: HDC) {
     if state.window_handle != hdc {
         state.original_gl_context = wglGetCurrentContext();
+
+        if WindowFromDC(state.window_handle) != WindowFromDC(hdc) {
+            state.window = None;
+        }
     }
 
     state.window_handle = hdc;
+}
+
+/// minimized and hidden windows have nothing to draw into. covered ones still do, since windows composites them anyway.
+/// asked every frame, since ShowWindow and SetWindowPos don't always send a message about it; asking sends none
+unsafe fn is_drawable(state: &EguiState, dimensions: (u32, u32)) -> bool {
+    if dimensions.0 == 0 || dimensions.1 == 0 {
+        return false;
+    }
+
+    let hwnd = WindowFromDC(state.window_handle);
+    !IsIconic(hwnd).as_bool() && IsWindowVisible(hwnd).as_bool()
+}
+
+/// uploads pending textures and draws the scheduler's cached frame
+unsafe fn draw(state: &mut EguiState, dimensions: (u32, u32)) -> Result<(), Error> {
+    let Some((clipped_primitives, pixels_per_point)) = state.scheduler.cached() else {
+        return Ok(());
+    };
 
     if wglMakeCurrent(state.window_handle, state.new_gl_context).is_err() {
         return Err(Error::CtxSwitch);
     }
 
-    let raw_input = get_raw_input(state)?;
-
-    let egui::FullOutput {
-        platform_output: _,
-        mut textures_delta,
-        shapes,
-        pixels_per_point,
-        viewport_output: _,
-    } = state.egui_ctx.run(raw_input, &*run_fn); // run through ui and get output
+    let mut textures_delta = std::mem::take(&mut state.pending_textures);
 
     for (id, image_delta) in textures_delta.set {
         state.painter.set_texture(id, &image_delta);
     }
 
-    // convert to meshes
-    let clipped_primitives = state.egui_ctx.tessellate(shapes, pixels_per_point);
-    let dimensions = get_screen_size()?;
-
     state.painter.paint_primitives(
         [dimensions.0, dimensions.1],
-        state.egui_ctx.pixels_per_point(),
-        &clipped_primitives,
+        pixels_per_point,
+        clipped_primitives,
     ); // actual opengl calls to render
 
     for id in textures_delta.free.drain(..) {
@@ -198,162 +756,349 @@ pub unsafe fn paint(hdc: HDC, run_fn: Box<dyn Fn(&egui::Context)>) -> Result<(),
     Ok(())
 }
 
-/// returns if you should skip calling original wndproc
-pub fn on_event(umsg: u32, wparam: usize, lparam: isize) -> Result<bool, Error> {
-    let state = unsafe {
-        match &mut STATE {
-            Some(s) => s,
-            None => return Err(Error::NotInit),
+/// runs the ui function and handles its output, tessellating into the scheduler's cache when it can be drawn
+unsafe fn run_ui(
+    state: &mut EguiState,
+    run_fn: Box<dyn Fn(&egui::Context)>,
+    now: f64,
+    dimensions: (u32, u32),
+    drawable: bool,
+) -> Result<(), Error> {
+    let raw_input = get_raw_input(state)?;
+
+    let egui::FullOutput {
+        platform_output,
+        textures_delta,
+        shapes,
+        pixels_per_point,
+        viewport_output,
+    } = run_egui(
+        &state.egui_ctx,
+        raw_input,
+        |ctx: &egui::Context| run_fn(ctx),
+        should_draw_cursor(state),
+        state.cursor_icon,
+    );
+
+    handle_platform_output(state, platform_output);
+    state.pending_textures.append(textures_delta);
+
+    let repaint_delay = viewport_output
+        .get(&ViewportId::ROOT)
+        .map_or(Duration::MAX, |viewport| viewport.repaint_delay);
+    state.scheduler.ran(now, repaint_delay);
+
+    if drawable {
+        // convert to meshes
+        let clipped_primitives = state.egui_ctx.tessellate(shapes, pixels_per_point);
+        state
+            .scheduler
+            .store(clipped_primitives, pixels_per_point, dimensions);
+    } else {
+        state.scheduler.mark_stale();
+    }
+
+    Ok(())
+}
+
+/// one egui frame with the software cursor on top, shared by paint and the ui thread
+pub(crate) fn run_egui(
+    egui_ctx: &egui::Context,
+    raw_input: RawInput,
+    mut run_fn: impl FnMut(&egui::Context),
+    draw_cursor: bool,
+    cursor_icon: CursorIcon,
+) -> egui::FullOutput {
+    egui_ctx.run(raw_input, |ctx| {
+        run_fn(ctx);
+
+        // drawn above windows and tooltips
+        if let Some(pos) = ctx.pointer_latest_pos().filter(|_| draw_cursor) {
+            let layer = LayerId::new(Order::Tooltip, Id::new("egui_glow_internal_cursor"));
+            cursor::paint_cursor(&ctx.layer_painter(layer), pos, cursor_icon);
         }
-    };
+    }) // run through ui and get output
+}
+
+fn should_draw_cursor(state: &EguiState) -> bool {
+    match state.options.software_cursor {
+        // the virtual cursors have nothing to do with the real one, so they always need drawing
+        _ if state.options.raw_mouse => state.focused,
+        _ if state.gamepad.cursor() == Some(state.pointer_pos) => true,
+        SoftwareCursor::Never => false,
+        SoftwareCursor::Always => true,
+        SoftwareCursor::WhenHidden => is_cursor_hidden(),
+    }
+}
+
+/// the parts of egui's output that go to windows: cursor, clipboard and links
+fn handle_platform_output(state: &mut EguiState, platform_output: egui::PlatformOutput) {
+    state.cursor_icon = platform_output.cursor_icon;
+
+    if !platform_output.copied_text.is_empty() {
+        if let Ok(mut clipboard) = state.clipboard.lock() {
+            clipboard.set_text(&platform_output.copied_text);
+        }
+    }
+
+    if let Some(open_url) = platform_output.open_url {
+        url::open(
+            &state.options.url_policy,
+            state.url_handler.clone(),
+            open_url,
+        );
+    }
+}
+
+/// call this from your wndproc hook with every message, then do what the disposition says
+///
+/// each consumed message gets the return value its documentation asks for, e.g. TRUE for WM_SETCURSOR.
+/// `wndproc` does all of this for you
+pub fn on_event(umsg: u32, wparam: usize, lparam: isize) -> Result<EventDisposition, Error> {
+    let state = state()?;
+
+    // WM_SETTEXT only takes effect once the host's wndproc had it, so the title is read on the message after
+    let read_title = std::mem::take(&mut state.title_changed);
+    let window_handle = state.window_handle;
+
+    let disposition = handle_event(state, umsg, wparam, lparam);
+    run_deferred();
+
+    if read_title {
+        // sends WM_GETTEXT back through here, so it waits until the state isn't in use anymore
+        let title = unsafe { get_window_title(window_handle) };
+
+        if let Ok(state) = crate::state() {
+            state.title = title;
+        }
+    }
+
+    Ok(disposition)
+}
+
+fn handle_event(
+    state: &mut EguiState,
+    umsg: u32,
+    wparam: usize,
+    lparam: isize,
+) -> EventDisposition {
+    if let Some(toggle_key) = state.options.toggle_key {
+        if matches!(umsg, WM_KEYDOWN | WM_SYSKEYDOWN | WM_KEYUP | WM_SYSKEYUP)
+            && get_key(wparam) == Some(toggle_key)
+        {
+            // bit 30 is the previous key state, so holding the key doesn't flicker
+            if matches!(umsg, WM_KEYDOWN | WM_SYSKEYDOWN) && lparam >> 30 & 1 == 0 {
+                apply_visibility(state, !state.visible);
+            }
+
+            return EventDisposition::Consume(0);
+        }
+    }
+
+    // from here on window messages keep this current
+    if state.window.is_none() {
+        state.window = Some(unsafe { query_window(WindowFromDC(state.window_handle)) });
+    }
+
+    if state.options.file_drop && state.drop_source.is_none() {
+        // set first, registering changes the window style, which sends messages straight back through here.
+        // run_deferred replaces it with what register ends up with
+        state.drop_source = Some(file_drop::DropSource::DropFiles);
+        state.register_drops = true;
+    }
+
+    if !state.visible && routing::is_input_message(umsg) {
+        // the host still sees these, and has to see the matching ups once the overlay is back
+        return track_host_presses(state, umsg, wparam, lparam, false);
+    }
+
+    let mut raw_class = None;
 
     match umsg {
+        WM_MOUSEMOVE | WM_LBUTTONDOWN | WM_LBUTTONDBLCLK | WM_LBUTTONUP | WM_RBUTTONDOWN
+        | WM_RBUTTONDBLCLK | WM_RBUTTONUP | WM_MBUTTONDOWN | WM_MBUTTONDBLCLK | WM_MBUTTONUP
+        | WM_XBUTTONDOWN | WM_XBUTTONDBLCLK | WM_XBUTTONUP | WM_MOUSEWHEEL | WM_MOUSEHWHEEL
+            if state.options.raw_mouse =>
+        {
+            // positions are useless when the host keeps recentering the cursor, WM_INPUT covers these
+        }
+        WM_INPUT if state.options.raw_mouse => {
+            raw_class = unsafe { handle_raw_input(state, lparam) };
+        }
+        WM_MOUSEMOVE | WM_LBUTTONDOWN | WM_LBUTTONDBLCLK | WM_LBUTTONUP | WM_RBUTTONDOWN
+        | WM_RBUTTONDBLCLK | WM_RBUTTONUP
+            if state.options.pointer_input && is_pointer_emulated() =>
+        {
+            // already handled through WM_POINTER*
+        }
         WM_MOUSEMOVE => {
             alter_modifiers(state, get_mouse_modifiers(wparam));
 
-            state.events.push(Event::PointerMoved(get_pos(lparam)));
+            if !state.tracking_mouse {
+                state.tracking_mouse = unsafe { track_mouse_leave(state) };
+            }
+
+            state.pointer_pos = get_pos(state, lparam);
+            state.events.push(Event::PointerMoved(state.pointer_pos));
         }
-        WM_LBUTTONDOWN | WM_LBUTTONDBLCLK => {
-            let modifiers = get_mouse_modifiers(wparam);
-            alter_modifiers(state, modifiers);
+        WM_MOUSELEAVE => {
+            state.tracking_mouse = false;
 
-            state.events.push(Event::PointerButton {
-                pos: get_pos(lparam),
-                button: PointerButton::Primary,
-                pressed: true,
-                modifiers,
-            });
+            if !state.options.raw_mouse {
+                state.events.push(Event::PointerGone);
+            }
         }
-        WM_LBUTTONUP => {
-            let modifiers = get_mouse_modifiers(wparam);
-            alter_modifiers(state, modifiers);
+        WM_DROPFILES if state.drop_source.is_some() => {
+            return unsafe { file_drop::handle_drop_files(state, wparam) };
+        }
+        WM_SETFOCUS => set_focused(state, true),
+        WM_SETTEXT => state.title_changed = true,
+        WM_SIZE | WM_MOVE => {
+            let hwnd = unsafe { WindowFromDC(state.window_handle) };
+            let window = state.window.get_or_insert_with(Default::default);
+
+            if umsg == WM_SIZE {
+                window.client_size = ((lparam & 0xFFFF) as u32, (lparam >> 16 & 0xFFFF) as u32);
+                // SIZE_MAXSHOW and SIZE_MAXHIDE are about other windows
+                if wparam as u32 <= SIZE_MAXIMIZED {
+                    window.minimized = wparam as u32 == SIZE_MINIMIZED;
+                    window.maximized = wparam as u32 == SIZE_MAXIMIZED;
+                }
+            }
 
-            state.events.push(Event::PointerButton {
-                pos: get_pos(lparam),
-                button: PointerButton::Primary,
-                pressed: false,
-                modifiers,
-            });
+            unsafe { query_placement(hwnd, window) };
         }
-        WM_RBUTTONDOWN | WM_RBUTTONDBLCLK => {
-            let modifiers = get_mouse_modifiers(wparam);
-            alter_modifiers(state, modifiers);
+        WM_DPICHANGED => {
+            // the new dpi is the same on both axes, and the host still has to resize the window itself
+            state.dpi_scale = (wparam & 0xFFFF) as f32 / 96.0;
+            state.scheduler.invalidate();
+        }
+        WM_KILLFOCUS => set_focused(state, false),
+        WM_ACTIVATE => {
+            let active = (wparam & 0xFFFF) as u32 != WA_INACTIVE;
 
-            state.events.push(Event::PointerButton {
-                pos: get_pos(lparam),
-                button: PointerButton::Secondary,
-                pressed: true,
-                modifiers,
-            });
+            // nothing is hovered while another window is on top
+            if !active && !state.options.raw_mouse {
+                state.events.push(Event::PointerGone);
+            }
+
+            set_focused(state, active);
+        }
+        WM_LBUTTONDOWN | WM_LBUTTONDBLCLK => {
+            push_mouse_button(state, wparam, lparam, PointerButton::Primary, true);
+        }
+        WM_LBUTTONUP => {
+            push_mouse_button(state, wparam, lparam, PointerButton::Primary, false);
+        }
+        WM_RBUTTONDOWN | WM_RBUTTONDBLCLK => {
+            push_mouse_button(state, wparam, lparam, PointerButton::Secondary, true);
         }
         WM_RBUTTONUP => {
-            let modifiers = get_mouse_modifiers(wparam);
-            alter_modifiers(state, modifiers);
-
-            state.events.push(Event::PointerButton {
-                pos: get_pos(lparam),
-                button: PointerButton::Secondary,
-                pressed: false,
-                modifiers,
-            });
+            push_mouse_button(state, wparam, lparam, PointerButton::Secondary, false);
         }
         WM_MBUTTONDOWN | WM_MBUTTONDBLCLK => {
-            let modifiers = get_mouse_modifiers(wparam);
-            alter_modifiers(state, modifiers);
-
-            state.events.push(Event::PointerButton {
-                pos: get_pos(lparam),
-                button: PointerButton::Middle,
-                pressed: true,
-                modifiers,
-            });
+            push_mouse_button(state, wparam, lparam, PointerButton::Middle, true);
         }
         WM_MBUTTONUP => {
-            let modifiers = get_mouse_modifiers(wparam);
-            alter_modifiers(state, modifiers);
-
-            state.events.push(Event::PointerButton {
-                pos: get_pos(lparam),
-                button: PointerButton::Middle,
-                pressed: false,
-                modifiers,
-            });
+            push_mouse_button(state, wparam, lparam, PointerButton::Middle, false);
         }
         WM_XBUTTONDOWN | WM_XBUTTONDBLCLK => {
-            let modifiers = get_mouse_modifiers(wparam);
-            alter_modifiers(state, modifiers);
-
-            state.events.push(Event::PointerButton {
-                pos: get_pos(lparam),
-                button: if (wparam as u32) >> 16u32 & XBUTTON1 as u32 != 0u32 {
-                    PointerButton::Extra1
-                } else if (wparam as u32) >> 16u32 & XBUTTON2 as u32 != 0u32 {
-                    PointerButton::Extra2
-                } else {
-                    unreachable!()
-                },
-                pressed: true,
-                modifiers,
-            });
+            push_mouse_button(state, wparam, lparam, get_x_button(wparam), true);
         }
         WM_XBUTTONUP => {
-            let modifiers = get_mouse_modifiers(wparam);
-            alter_modifiers(state, modifiers);
-
-            state.events.push(Event::PointerButton {
-                pos: get_pos(lparam),
-                button: if (wparam as u32) >> 16u32 & XBUTTON1 as u32 != 0u32 {
-                    PointerButton::Extra1
-                } else if (wparam as u32) >> 16u32 & XBUTTON2 as u32 != 0u32 {
-                    PointerButton::Extra2
-                } else {
-                    unreachable!()
-                },
-                pressed: false,
-                modifiers,
-            });
+            push_mouse_button(state, wparam, lparam, get_x_button(wparam), false);
+        }
+        WM_CAPTURECHANGED
+            if state.has_capture && lparam != unsafe { WindowFromDC(state.window_handle) }.0 =>
+        {
+            // someone else took the mouse mid-drag, the button up won't come to us
+            state.has_capture = false;
+
+            let modifiers = state.modifiers.unwrap_or_default();
+            for button in std::mem::take(&mut state.captured_buttons) {
+                state.events.push(Event::PointerButton {
+                    pos: state.pointer_pos,
+                    button,
+                    pressed: false,
+                    modifiers,
+                });
+            }
         }
         WM_CHAR => {
+            if state.options.synthesize_text {
+                // the host does translate messages after all, so let WM_CHAR take over
+                state.host_translates = true;
+                state.dead_key = None;
+            }
+
             if let Some(ch) = char::from_u32(wparam as _) {
                 if !ch.is_control() {
-                    state.events.push(Event::Text(ch.into()));
+                    // skip characters that were already synthesized from the matching WM_KEYDOWN
+                    if state.synthesized_text.front() == Some(&ch) {
+                        state.synthesized_text.pop_front();
+                    } else {
+                        state.synthesized_text.clear();
+                        state.events.push(Event::Text(ch.into()));
+                    }
                 }
             }
         }
         WM_MOUSEWHEEL => {
-            alter_modifiers(state, get_mouse_modifiers(wparam));
-
-            let delta = (wparam >> 16) as i16 as f32 * 10.0 / WHEEL_DELTA as f32;
+            let modifiers = get_mouse_modifiers(wparam);
+            alter_modifiers(state, modifiers);
 
-            if wparam & MK_CONTROL.0 as usize != 0 {
-                state
-                    .events
-                    .push(Event::Zoom(if delta > 0.0 { 1.5 } else { 0.5 }));
-            } else {
-                state.events.push(Event::Scroll(Vec2::new(0.0, delta)));
-            }
+            push_wheel(state, (wparam >> 16) as i16, false, modifiers);
         }
         WM_MOUSEHWHEEL => {
-            alter_modifiers(state, get_mouse_modifiers(wparam));
-
-            let delta = (wparam >> 16) as i16 as f32 * 10.0 / WHEEL_DELTA as f32;
+            let modifiers = get_mouse_modifiers(wparam);
+            alter_modifiers(state, modifiers);
 
-            if wparam & MK_CONTROL.0 as usize != 0 {
-                state
-                    .events
-                    .push(Event::Zoom(if delta > 0. { 1.5 } else { 0.5 }));
-            } else {
-                state.events.push(Event::Scroll(Vec2::new(delta, 0.0)));
-            }
+            push_wheel(state, (wparam >> 16) as i16, true, modifiers);
+        }
+        msg @ (WM_POINTERDOWN | WM_POINTERUPDATE | WM_POINTERUP | WM_POINTERCAPTURECHANGED)
+            if state.options.pointer_input =>
+        {
+            unsafe { handle_pointer(state, msg, wparam) };
+        }
+        WM_SETCURSOR
+            if (lparam & 0xFFFF) as u32 == HTCLIENT
+                && is_pointer_over_egui(state)
+                && state.options.input_policy.blocks(&RoutedMessage {
+                    umsg,
+                    wparam,
+                    lparam,
+                    class: MessageClass::MouseMove,
+                    egui_ctx: &state.egui_ctx,
+                }) =>
+        {
+            unsafe { apply_cursor_icon(state.cursor_icon) };
+
+            return EventDisposition::Consume(1);
+        }
+        WM_GETDLGCODE
+            if state.visible
+                && state.egui_ctx.wants_keyboard_input()
+                && !matches!(
+                    state.options.input_policy.mode_for(MessageClass::Keyboard),
+                    InputMode::PassThrough
+                ) =>
+        {
+            // otherwise a host dialog keeps tab, enter and arrows for its own navigation
+            return EventDisposition::Consume((DLGC_WANTALLKEYS | DLGC_WANTCHARS) as isize);
         }
         msg @ (WM_KEYDOWN | WM_SYSKEYDOWN) => {
             let modifiers = get_key_modifiers(msg);
             state.modifiers = Some(modifiers);
 
             if let Some(key) = get_key(wparam) {
+                if !state.pressed_keys.contains(&key) {
+                    state.pressed_keys.push(key);
+                }
+
                 if key == Key::V && modifiers.ctrl {
-                    if let Some(clipboard) = get_clipboard_text() {
-                        state.events.push(Event::Text(clipboard));
+                    let text = state.clipboard.lock().ok().and_then(|mut c| c.get_text());
+                    if let Some(text) = text {
+                        state.events.push(Event::Paste(text));
                     }
                 }
 
@@ -373,12 +1118,24 @@ pub fn on_event(umsg: u32, wparam: usize, lparam: isize) -> Result<bool, Error>
                     physical_key: Some(key),
                 });
             }
+
+            // alt+letter is a menu accelerator, TranslateMessage turns it into WM_SYSCHAR rather than text
+            if msg == WM_KEYDOWN && state.options.synthesize_text && !state.host_translates {
+                if let Some(text) = unsafe { translate_key(state, wparam, lparam) } {
+                    for ch in text.chars().filter(|ch| !ch.is_control()) {
+                        state.synthesized_text.push_back(ch);
+                        state.events.push(Event::Text(ch.into()));
+                    }
+                }
+            }
         }
         msg @ (WM_KEYUP | WM_SYSKEYUP) => {
             let modifiers = get_key_modifiers(msg);
             state.modifiers = Some(modifiers);
 
             if let Some(key) = get_key(wparam) {
+                state.pressed_keys.retain(|pressed| *pressed != key);
+
                 state.events.push(Event::Key {
                     pressed: false,
                     modifiers,
@@ -391,34 +1148,506 @@ pub fn on_event(umsg: u32, wparam: usize, lparam: isize) -> Result<bool, Error>
         _ => {}
     }
 
-    Ok((state.egui_ctx.wants_pointer_input()
-        && matches!(
+    let Some(class) = raw_class.or_else(|| routing::message_class(umsg)) else {
+        return EventDisposition::PassThrough;
+    };
+
+    let blocked = state.options.input_policy.blocks(&RoutedMessage {
+        umsg,
+        wparam,
+        lparam,
+        class,
+        egui_ctx: &state.egui_ctx,
+    });
+
+    if blocked && umsg == WM_INPUT {
+        return EventDisposition::DefWindowProc;
+    }
+
+    track_host_presses(state, umsg, wparam, lparam, blocked)
+}
+
+/// a wndproc hook body: runs on_event and applies its disposition, calling original when the host should see the message
+///
+/// # Safety
+/// original has to be the wndproc that was replaced for hwnd, and the hook has to be installed with
+/// SetWindowLongPtrW so messages arrive as unicode
+pub unsafe fn wndproc(
+    original: WNDPROC,
+    hwnd: HWND,
+    umsg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match on_event(umsg, wparam.0, lparam.0) {
+        Ok(EventDisposition::Consume(result)) => LRESULT(result),
+        Ok(EventDisposition::Forward {
             umsg,
-            WM_MOUSEMOVE
-                | WM_LBUTTONDOWN
-                | WM_LBUTTONDBLCLK
-                | WM_LBUTTONUP
-                | WM_RBUTTONDOWN
-                | WM_RBUTTONDBLCLK
-                | WM_RBUTTONUP
-                | WM_MBUTTONDOWN
-                | WM_MBUTTONDBLCLK
-                | WM_MBUTTONUP
-                | WM_MOUSEWHEEL
-                | WM_MOUSEHWHEEL
-        ))
-        || (state.egui_ctx.wants_keyboard_input()
-            && matches!(
-                umsg,
-                WM_CHAR | WM_KEYDOWN | WM_SYSKEYDOWN | WM_KEYUP | WM_SYSKEYUP
-            )))
-}
-
-fn get_pos(lparam: isize) -> Pos2 {
+            wparam,
+            lparam,
+        }) => CallWindowProcW(original, hwnd, umsg, WPARAM(wparam), LPARAM(lparam)),
+        Ok(EventDisposition::DefWindowProc) => DefWindowProcW(hwnd, umsg, wparam, lparam),
+        Ok(EventDisposition::PassThrough) | Err(_) => {
+            CallWindowProcW(original, hwnd, umsg, wparam, lparam)
+        }
+    }
+}
+
+/// does what had to wait until the caller was done with the state. capture and window style changes send messages
+/// right away and the visibility callback may call anything, all of which would alias the state still being used
+fn run_deferred() {
+    let Ok(state) = state() else {
+        return;
+    };
+
+    let hwnd = unsafe { WindowFromDC(state.window_handle) };
+    let register_drops = std::mem::take(&mut state.register_drops);
+    let capture_request = state.capture_request.take();
+    let visibility_change = state
+        .visibility_changed
+        .take()
+        .zip(state.visibility_callback.clone());
+
+    // nothing below touches state again, other than through a fresh borrow once register is done
+    if register_drops {
+        let drop_source = unsafe { file_drop::register(hwnd) };
+
+        if let Ok(state) = crate::state() {
+            state.drop_source = Some(drop_source);
+        }
+    }
+
+    match capture_request {
+        Some(true) => unsafe {
+            SetCapture(hwnd);
+        },
+        Some(false) => {
+            let _ = unsafe { ReleaseCapture() };
+        }
+        None => {}
+    }
+
+    if let Some((visible, callback)) = visibility_change {
+        callback(visible);
+    }
+}
+
+/// remembers the keys and buttons the host saw go down, so it always gets to see them go up again;
+/// otherwise opening a modal overlay while walking would keep the character walking
+fn track_host_presses(
+    state: &mut EguiState,
+    umsg: u32,
+    wparam: usize,
+    lparam: isize,
+    blocked: bool,
+) -> EventDisposition {
+    if let Some(press) = routing::release_of(umsg, wparam) {
+        if let Some(index) = state.host_pressed.iter().position(|p| *p == press) {
+            state.host_pressed.remove(index);
+            return EventDisposition::PassThrough;
+        }
+    } else if let Some(press) = routing::press_of(umsg, wparam) {
+        let held = state.host_pressed.iter().position(|p| *p == press);
+
+        match (blocked, held) {
+            (false, None) => state.host_pressed.push(press),
+            // a key the host saw go down starts repeating into egui, let go of it on the host side
+            (true, Some(index)) => {
+                if let Some(key_up) = routing::key_up_for(umsg, wparam, lparam) {
+                    state.host_pressed.remove(index);
+                    return key_up;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if blocked {
+        EventDisposition::Consume(0)
+    } else {
+        EventDisposition::PassThrough
+    }
+}
+
+/// moves the virtual cursor and presses buttons from raw mouse input, returns None for other devices
+unsafe fn handle_raw_input(state: &mut EguiState, lparam: isize) -> Option<MessageClass> {
+    // not exported by Win32_UI_Input
+    const MOUSE_MOVE_ABSOLUTE: u16 = 0x01;
+
+    let mut input = RAWINPUT::default();
+    let mut size = std::mem::size_of::<RAWINPUT>() as u32;
+
+    if GetRawInputData(
+        HRAWINPUT(lparam),
+        RID_INPUT,
+        Some(&mut input as *mut _ as _),
+        &mut size,
+        std::mem::size_of::<RAWINPUTHEADER>() as u32,
+    ) == u32::MAX
+    {
+        return None;
+    }
+
+    if input.header.dwType != RIM_TYPEMOUSE.0 {
+        return None;
+    }
+
+    let mouse = input.data.mouse;
+    let button_flags = mouse.Anonymous.Anonymous.usButtonFlags as u32;
+    let button_data = mouse.Anonymous.Anonymous.usButtonData as i16;
+
+    // one raw input can carry everything at once, buttons and wheels matter most for routing
+    let class = if button_flags & (RI_MOUSE_WHEEL | RI_MOUSE_HWHEEL) != 0 {
+        MessageClass::Wheel
+    } else if button_flags != 0 {
+        MessageClass::MouseButton
+    } else {
+        MessageClass::MouseMove
+    };
+
+    let pixels_per_point = pixels_per_point(state);
+    let Ok(screen_rect) = screen_rect(state) else {
+        return Some(class);
+    };
+
+    let pos = state.virtual_cursor.unwrap_or(screen_rect.center());
+    let pos = if mouse.usFlags & MOUSE_MOVE_ABSOLUTE == 0 {
+        let delta = Vec2::new(mouse.lLastX as f32, mouse.lLastY as f32) / pixels_per_point;
+        screen_rect.clamp(pos + delta)
+    } else {
+        // tablets and remote desktop report absolute positions, which there is no sane mapping for here
+        pos
+    };
+
+    if state.virtual_cursor != Some(pos) {
+        state.virtual_cursor = Some(pos);
+        state.events.push(Event::PointerMoved(pos));
+    }
+
+    let modifiers = state.modifiers.unwrap_or_default();
+
+    for (down, up, button) in [
+        (
+            RI_MOUSE_LEFT_BUTTON_DOWN,
+            RI_MOUSE_LEFT_BUTTON_UP,
+            PointerButton::Primary,
+        ),
+        (
+            RI_MOUSE_RIGHT_BUTTON_DOWN,
+            RI_MOUSE_RIGHT_BUTTON_UP,
+            PointerButton::Secondary,
+        ),
+        (
+            RI_MOUSE_MIDDLE_BUTTON_DOWN,
+            RI_MOUSE_MIDDLE_BUTTON_UP,
+            PointerButton::Middle,
+        ),
+        (
+            RI_MOUSE_BUTTON_4_DOWN,
+            RI_MOUSE_BUTTON_4_UP,
+            PointerButton::Extra1,
+        ),
+        (
+            RI_MOUSE_BUTTON_5_DOWN,
+            RI_MOUSE_BUTTON_5_UP,
+            PointerButton::Extra2,
+        ),
+    ] {
+        for (flag, pressed) in [(down, true), (up, false)] {
+            if button_flags & flag != 0 {
+                state.events.push(Event::PointerButton {
+                    pos,
+                    button,
+                    pressed,
+                    modifiers,
+                });
+            }
+        }
+    }
+
+    if button_flags & RI_MOUSE_WHEEL != 0 {
+        push_wheel(state, button_data, false, modifiers);
+    }
+
+    if button_flags & RI_MOUSE_HWHEEL != 0 {
+        push_wheel(state, button_data, true, modifiers);
+    }
+
+    Some(class)
+}
+
+/// delta is in WHEEL_DELTA units, but precision touchpads and smooth wheels send fractions of a notch
+fn push_wheel(state: &mut EguiState, delta: i16, horizontal: bool, modifiers: Modifiers) {
+    let notches = delta as f32 / WHEEL_DELTA as f32;
+
+    let delta = if horizontal || modifiers.shift {
+        Vec2::new(notches, 0.0)
+    } else {
+        Vec2::new(0.0, notches)
+    };
+
+    state.events.push(Event::MouseWheel {
+        unit: MouseWheelUnit::Line,
+        delta,
+        modifiers,
+    });
+
+    if modifiers.ctrl {
+        state
+            .events
+            .push(Event::Zoom(state.options.zoom_step.powf(notches)));
+    } else {
+        state
+            .events
+            .push(Event::Scroll(delta * state.options.scroll_speed));
+    }
+}
+
+/// pushes a button event for WM_*BUTTON*, and holds on to the mouse while egui is dragging
+/// so the button up still arrives when it's released outside the window
+fn push_mouse_button(
+    state: &mut EguiState,
+    wparam: usize,
+    lparam: isize,
+    button: PointerButton,
+    pressed: bool,
+) {
+    let modifiers = get_mouse_modifiers(wparam);
+    alter_modifiers(state, modifiers);
+
+    state.pointer_pos = get_pos(state, lparam);
+    state.events.push(Event::PointerButton {
+        pos: state.pointer_pos,
+        button,
+        pressed,
+        modifiers,
+    });
+
+    if pressed {
+        if state.has_capture || state.egui_ctx.wants_pointer_input() {
+            if !state.has_capture {
+                state.capture_request = Some(true);
+                state.has_capture = true;
+            }
+
+            if !state.captured_buttons.contains(&button) {
+                state.captured_buttons.push(button);
+            }
+        }
+    } else {
+        state
+            .captured_buttons
+            .retain(|captured| *captured != button);
+
+        if state.has_capture && state.captured_buttons.is_empty() {
+            // cleared first, releasing sends WM_CAPTURECHANGED right away
+            state.has_capture = false;
+            state.capture_request = Some(false);
+        }
+    }
+}
+
+fn get_x_button(wparam: usize) -> PointerButton {
+    if (wparam as u32) >> 16u32 & XBUTTON1 as u32 != 0u32 {
+        PointerButton::Extra1
+    } else if (wparam as u32) >> 16u32 & XBUTTON2 as u32 != 0u32 {
+        PointerButton::Extra2
+    } else {
+        unreachable!()
+    }
+}
+
+/// asks for a WM_MOUSELEAVE once the mouse leaves the window, returns if that worked
+unsafe fn track_mouse_leave(state: &EguiState) -> bool {
+    let mut event = TRACKMOUSEEVENT {
+        cbSize: std::mem::size_of::<TRACKMOUSEEVENT>() as u32,
+        dwFlags: TME_LEAVE,
+        hwndTrack: WindowFromDC(state.window_handle),
+        dwHoverTime: 0,
+    };
+
+    TrackMouseEvent(&mut event).is_ok()
+}
+
+fn is_pointer_over_egui(state: &EguiState) -> bool {
+    state.egui_ctx.is_pointer_over_area() || state.egui_ctx.is_using_pointer()
+}
+
+unsafe fn apply_cursor_icon(icon: CursorIcon) {
+    match cursor::system_cursor(icon) {
+        Some(name) => {
+            if let Ok(cursor) = LoadCursorW(None, name) {
+                SetCursor(cursor);
+            }
+        }
+        None => {
+            SetCursor(None);
+        }
+    }
+}
+
+/// on focus loss the key ups go to some other window, so release everything here
+fn set_focused(state: &mut EguiState, focused: bool) {
+    if state.focused == focused {
+        return;
+    }
+
+    state.focused = focused;
+    state.events.push(Event::WindowFocused(focused));
+
+    if !focused {
+        release_keys(state);
+    }
+}
+
+fn release_keys(state: &mut EguiState) {
+    for key in std::mem::take(&mut state.pressed_keys) {
+        state.events.push(Event::Key {
+            pressed: false,
+            modifiers: Modifiers::NONE,
+            key,
+            repeat: false,
+            physical_key: Some(key),
+        });
+    }
+
+    state.modifiers = Some(Modifiers::NONE);
+}
+
+/// hiding lets go of everything egui was holding, since the releases will go straight to the host
+fn apply_visibility(state: &mut EguiState, visible: bool) {
+    if state.visible == visible {
+        return;
+    }
+
+    state.visible = visible;
+    state.scheduler.invalidate();
+
+    if !visible {
+        release_keys(state);
+
+        let modifiers = state.modifiers.unwrap_or_default();
+        for button in std::mem::take(&mut state.captured_buttons) {
+            state.events.push(Event::PointerButton {
+                pos: state.pointer_pos,
+                button,
+                pressed: false,
+                modifiers,
+            });
+        }
+
+        if state.has_capture {
+            state.has_capture = false;
+            state.capture_request = Some(false);
+        }
+
+        state.events.push(Event::PointerGone);
+    }
+
+    state.visibility_changed = Some(visible);
+}
+
+/// client area pixels from lparam, in points
+fn get_pos(state: &EguiState, lparam: isize) -> Pos2 {
     let x = (lparam & 0xFFFF) as i16 as f32;
     let y = (lparam >> 16 & 0xFFFF) as i16 as f32;
 
-    Pos2::new(x, y)
+    Pos2::new(x, y) / pixels_per_point(state)
+}
+
+/// touch and pen input, with the primary pointer also acting as the mouse
+unsafe fn handle_pointer(state: &mut EguiState, msg: u32, wparam: usize) {
+    let pointer_id = (wparam & 0xFFFF) as u32;
+
+    let mut info = POINTER_INFO::default();
+    if GetPointerInfo(pointer_id, &mut info).is_err() {
+        return;
+    }
+
+    // mouse pointers only show up after EnableMouseInPointer, and WM_MOUSE* covers those
+    if info.pointerType != PT_TOUCH && info.pointerType != PT_PEN {
+        return;
+    }
+
+    let mut point = info.ptPixelLocation;
+    let _ = ScreenToClient(WindowFromDC(state.window_handle), &mut point);
+    let pos = Pos2::new(point.x as f32, point.y as f32) / pixels_per_point(state);
+
+    let flags = info.pointerFlags;
+    let phase = match msg {
+        _ if flags.contains(POINTER_FLAG_CANCELED) => Some(TouchPhase::Cancel),
+        WM_POINTERCAPTURECHANGED => Some(TouchPhase::Cancel),
+        WM_POINTERDOWN => Some(TouchPhase::Start),
+        WM_POINTERUP => Some(TouchPhase::End),
+        // a pen hovering above the screen only moves the mouse
+        _ if flags.contains(POINTER_FLAG_INCONTACT) => Some(TouchPhase::Move),
+        _ => None,
+    };
+
+    if let Some(phase) = phase {
+        state.events.push(Event::Touch {
+            device_id: TouchDeviceId(info.sourceDevice.0 as u64),
+            id: TouchId(pointer_id as u64),
+            phase,
+            pos,
+            force: get_pointer_pressure(pointer_id, info.pointerType),
+        });
+    }
+
+    if !flags.contains(POINTER_FLAG_PRIMARY) {
+        return;
+    }
+
+    let modifiers = state.modifiers.unwrap_or_default();
+    state.events.push(Event::PointerMoved(pos));
+
+    match phase {
+        Some(TouchPhase::Start) => state.events.push(Event::PointerButton {
+            pos,
+            button: PointerButton::Primary,
+            pressed: true,
+            modifiers,
+        }),
+        Some(TouchPhase::End | TouchPhase::Cancel) => {
+            state.events.push(Event::PointerButton {
+                pos,
+                button: PointerButton::Primary,
+                pressed: false,
+                modifiers,
+            });
+
+            // a lifted finger doesn't hover anywhere, but a pen still might
+            if info.pointerType == PT_TOUCH {
+                state.events.push(Event::PointerGone);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// pressure from 0 to 1, if the device reports it
+unsafe fn get_pointer_pressure(pointer_id: u32, pointer_type: POINTER_INPUT_TYPE) -> Option<f32> {
+    let (mask, pressure) = if pointer_type == PT_PEN {
+        let mut info = POINTER_PEN_INFO::default();
+        GetPointerPenInfo(pointer_id, &mut info).ok()?;
+        (info.penMask & PEN_MASK_PRESSURE, info.pressure)
+    } else {
+        let mut info = POINTER_TOUCH_INFO::default();
+        GetPointerTouchInfo(pointer_id, &mut info).ok()?;
+        (info.touchMask & TOUCH_MASK_PRESSURE, info.pressure)
+    };
+
+    (mask != 0).then(|| pressure as f32 / 1024.0)
+}
+
+/// checks if the current mouse message was made up by windows from touch or pen input
+///
+/// https://learn.microsoft.com/en-us/windows/win32/tablet/system-events-and-mouse-messages
+fn is_pointer_emulated() -> bool {
+    const SIGNATURE_MASK: u32 = 0xFFFFFF00;
+    const MI_WP_SIGNATURE: u32 = 0xFF515700;
+
+    unsafe { GetMessageExtraInfo().0 as u32 & SIGNATURE_MASK == MI_WP_SIGNATURE }
 }
 
 fn get_key_modifiers(msg: u32) -> Modifiers {
@@ -473,6 +1702,73 @@ fn get_key(wparam: usize) -> Option<Key> {
     }
 }
 
+/// does what TranslateMessage would do for a key press, but without touching the system's dead key state
+unsafe fn translate_key(state: &mut EguiState, wparam: usize, lparam: isize) -> Option<String> {
+    // keyboard state is left alone, only works on windows 10 1607 and later
+    const NO_STATE_CHANGE: u32 = 1 << 2;
+
+    let mut keyboard_state = [0u8; 256];
+    if GetKeyboardState(&mut keyboard_state).is_err() {
+        return None;
+    }
+
+    let virtual_key = wparam as u32;
+    let scan_code = (lparam >> 16 & 0xFF) as u32;
+    let layout = GetKeyboardLayout(0);
+    let mut buffer = [0u16; 8];
+
+    let len = ToUnicodeEx(
+        virtual_key,
+        scan_code,
+        &keyboard_state,
+        &mut buffer,
+        NO_STATE_CHANGE,
+        layout,
+    );
+
+    if len == 0 {
+        // modifiers and such, keep any pending dead key around
+        return None;
+    }
+
+    let len = match state.dead_key.take() {
+        Some(dead_key) => {
+            // feed the dead key back in so windows does the composing, then let the next key consume it
+            ToUnicodeEx(
+                dead_key.virtual_key,
+                dead_key.scan_code,
+                &dead_key.keyboard_state,
+                &mut buffer,
+                0,
+                layout,
+            );
+            ToUnicodeEx(
+                virtual_key,
+                scan_code,
+                &keyboard_state,
+                &mut buffer,
+                0,
+                layout,
+            )
+        }
+        None if len < 0 => {
+            state.dead_key = Some(DeadKey {
+                virtual_key,
+                scan_code,
+                keyboard_state,
+            });
+            return None;
+        }
+        None => len,
+    };
+
+    if len <= 0 {
+        return None;
+    }
+
+    Some(String::from_utf16_lossy(&buffer[..len as usize]))
+}
+
 fn get_mouse_modifiers(wparam: usize) -> Modifiers {
     Modifiers {
         alt: false,
@@ -483,43 +1779,150 @@ fn get_mouse_modifiers(wparam: usize) -> Modifiers {
     }
 }
 
-fn get_clipboard_text() -> Option<String> {
-    WindowsClipboardContext.get_contents().ok()
-}
-
 unsafe fn get_raw_input(state: &mut EguiState) -> Result<RawInput, Error> {
+    let time = state.clock.now();
+
     Ok(RawInput {
         modifiers: state.modifiers.unwrap_or_default(),
-        events: std::mem::take(&mut state.events),
-        screen_rect: Some(get_screen_rect()?),
-        time: Some(get_system_time()),
-        max_texture_side: None,
-        predicted_dt: 1.0 / 60.0,
-        hovered_files: vec![],
-        dropped_files: vec![],
-        focused: true,
+        events: state.events.drain(),
+        screen_rect: Some(screen_rect(state)?),
+        time: Some(time),
+        max_texture_side: Some(state.max_texture_side),
+        predicted_dt: state.frame_timer.frame(time),
+        hovered_files: state.hovered_files.clone(),
+        dropped_files: std::mem::take(&mut state.dropped_files),
+        focused: state.focused,
+        viewports: std::iter::once((ViewportId::ROOT, get_viewport_info(state))).collect(),
         ..Default::default()
     })
 }
 
-fn get_system_time() -> f64 {
-    let mut time = 0;
-    unsafe {
-        let _ = NtQuerySystemTime(&mut time); // returns NTSTATUS
+/// the host window's metrics in points, with screen coordinates for the rects
+unsafe fn get_viewport_info(state: &EguiState) -> ViewportInfo {
+    let hwnd = WindowFromDC(state.window_handle);
+    let pixels_per_point = pixels_per_point(state);
+    let to_rect = |rect: RECT| {
+        Rect::from_min_max(
+            Pos2::new(rect.left as f32, rect.top as f32) / pixels_per_point,
+            Pos2::new(rect.right as f32, rect.bottom as f32) / pixels_per_point,
+        )
+    };
+
+    let window = match state.window {
+        Some(window) => window,
+        None => query_window(hwnd),
+    };
+
+    let inner_rect = window.client_origin.map(|origin| {
+        to_rect(RECT {
+            left: origin.x,
+            top: origin.y,
+            right: origin.x + window.client_size.0 as i32,
+            bottom: origin.y + window.client_size.1 as i32,
+        })
+    });
+
+    ViewportInfo {
+        title: Some(state.title.clone()),
+        native_pixels_per_point: Some(native_pixels_per_point(state)),
+        monitor_size: window.monitor_rect.map(|monitor| to_rect(monitor).size()),
+        inner_rect,
+        outer_rect: window.outer_rect.map(to_rect),
+        minimized: Some(window.minimized),
+        maximized: Some(window.maximized),
+        // borderless and exclusive fullscreen both cover the whole monitor
+        fullscreen: window
+            .outer_rect
+            .zip(window.monitor_rect)
+            .map(|(outer, monitor)| outer == monitor),
+        focused: Some(state.focused),
+        ..Default::default()
     }
+}
+
+/// everything about the window at once, for when no message has said anything yet
+unsafe fn query_window(hwnd: HWND) -> WindowMetrics {
+    let mut client = RECT::default();
+    let _ = GetClientRect(hwnd, &mut client);
+
+    let mut window = WindowMetrics {
+        client_size: (client.right as u32, client.bottom as u32),
+        minimized: IsIconic(hwnd).as_bool(),
+        maximized: IsZoomed(hwnd).as_bool(),
+        ..Default::default()
+    };
+    query_placement(hwnd, &mut window);
 
-    // nanoseconds
-    (time as f64) / 10_000_000.0
+    window
 }
 
-pub fn get_screen_size() -> Result<(u32, u32), Error> {
-    let state = unsafe {
-        match &mut STATE {
-            Some(s) => s,
-            None => return Err(Error::NotInit),
-        }
+/// where the window and its monitor are, which only changes along with WM_MOVE and WM_SIZE
+unsafe fn query_placement(hwnd: HWND, window: &mut WindowMetrics) {
+    let mut origin = POINT::default();
+    window.client_origin = ClientToScreen(hwnd, &mut origin)
+        .as_bool()
+        .then_some(origin);
+
+    let mut outer = RECT::default();
+    window.outer_rect = GetWindowRect(hwnd, &mut outer).is_ok().then_some(outer);
+
+    let mut monitor = MONITORINFO {
+        cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+        ..Default::default()
+    };
+    window.monitor_rect = GetMonitorInfoW(
+        MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST),
+        &mut monitor,
+    )
+    .as_bool()
+    .then_some(monitor.rcMonitor);
+}
+
+/// from another thread this sends WM_GETTEXT and waits for the window's thread, so it's kept out of paint
+unsafe fn get_window_title(window_handle: HDC) -> String {
+    let mut title = [0; 256];
+    let title_len = GetWindowTextW(WindowFromDC(window_handle), &mut title).max(0) as usize;
+
+    String::from_utf16_lossy(&title[..title_len])
+}
+
+/// what egui draws at: the native scaling times egui's zoom factor, which ctrl plus and minus change
+fn pixels_per_point(state: &EguiState) -> f32 {
+    native_pixels_per_point(state) * state.egui_ctx.zoom_factor()
+}
+
+/// the monitor's scaling times the user's
+fn native_pixels_per_point(state: &EguiState) -> f32 {
+    state.dpi_scale * state.options.ui_scale
+}
+
+fn get_dpi_scale(window_handle: HDC) -> f32 {
+    // 0 means the window handle is bad, and 96 is the dpi that counts as 100%
+    match unsafe { GetDpiForWindow(WindowFromDC(window_handle)) } {
+        0 => 1.0,
+        dpi => dpi as f32 / 96.0,
+    }
+}
+
+fn is_cursor_hidden() -> bool {
+    let mut info = CURSORINFO {
+        cbSize: std::mem::size_of::<CURSORINFO>() as u32,
+        ..Default::default()
     };
 
+    unsafe { GetCursorInfo(&mut info) }.is_ok() && info.flags.0 & CURSOR_SHOWING.0 == 0
+}
+
+/// the client area in pixels, kept from WM_SIZE once on_event has run
+pub fn get_screen_size() -> Result<(u32, u32), Error> {
+    screen_size(state()?)
+}
+
+fn screen_size(state: &EguiState) -> Result<(u32, u32), Error> {
+    if let Some(window) = &state.window {
+        return Ok(window.client_size);
+    }
+
     let mut rect = RECT::default();
     unsafe {
         if GetClientRect(WindowFromDC(state.window_handle), &mut rect).is_err() {
@@ -533,14 +1936,16 @@ pub fn get_screen_size() -> Result<(u32, u32), Error> {
     ))
 }
 
-fn get_screen_rect() -> Result<Rect, Error> {
-    let size = get_screen_size()?;
+/// the client area in points
+fn screen_rect(state: &EguiState) -> Result<Rect, Error> {
+    let size = screen_size(state)?;
+    let pixels_per_point = pixels_per_point(state);
 
     Ok(Rect {
         min: Pos2::ZERO,
         max: Pos2 {
-            x: size.0 as f32,
-            y: size.1 as f32,
+            x: size.0 as f32 / pixels_per_point,
+            y: size.1 as f32 / pixels_per_point,
         },
     })
 }
@@ -0,0 +1,187 @@
+use std::sync::Arc;
+use windows::Win32::UI::WindowsAndMessaging::*;
+
+/// the kinds of input messages an InputPolicy can route
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MessageClass {
+    /// WM_KEYDOWN, WM_KEYUP, WM_SYSKEYDOWN, WM_SYSKEYUP and WM_CHAR
+    Keyboard,
+    /// WM_MOUSEMOVE, and raw mouse motion
+    MouseMove,
+    /// WM_*BUTTONDOWN, WM_*BUTTONUP and WM_*BUTTONDBLCLK, and raw mouse buttons
+    MouseButton,
+    /// WM_MOUSEWHEEL and WM_MOUSEHWHEEL, and raw mouse wheels
+    Wheel,
+    /// WM_POINTERDOWN, WM_POINTERUPDATE and WM_POINTERUP
+    Touch,
+}
+
+/// what the wndproc hook should do with a message after on_event saw it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventDisposition {
+    /// call the original wndproc as usual
+    PassThrough,
+    /// don't call the original wndproc, return this LRESULT instead
+    Consume(isize),
+    /// don't deliver the original message, call the original wndproc with this one instead and return its result
+    Forward {
+        umsg: u32,
+        wparam: usize,
+        lparam: isize,
+    },
+    /// don't call the original wndproc, return what DefWindowProcW gives for the message instead;
+    /// WM_INPUT needs this so windows can clean up after it
+    DefWindowProc,
+}
+
+/// a message being routed, handed to InputMode::Custom
+pub struct RoutedMessage<'a> {
+    pub umsg: u32,
+    pub wparam: usize,
+    pub lparam: isize,
+    pub class: MessageClass,
+    pub egui_ctx: &'a egui::Context,
+}
+
+/// decides if a message is kept from the host
+#[derive(Clone, Default)]
+pub enum InputMode {
+    /// the host gets everything
+    PassThrough,
+    /// the host gets nothing, like for a modal menu
+    BlockAll,
+    /// the host gets whatever egui doesn't want, i.e. the pointer isn't over an egui area or no text field is focused
+    #[default]
+    BlockWanted,
+    /// return true to keep the message from the host
+    Custom(Arc<dyn Fn(&RoutedMessage) -> bool + Send + Sync>),
+}
+
+impl std::fmt::Debug for InputMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PassThrough => write!(f, "PassThrough"),
+            Self::BlockAll => write!(f, "BlockAll"),
+            Self::BlockWanted => write!(f, "BlockWanted"),
+            Self::Custom(_) => write!(f, "Custom(..)"),
+        }
+    }
+}
+
+/// which input messages on_event keeps from the host, with optional overrides per message class
+///
+/// for example a passive hud that only takes the mouse wheel:
+/// `InputPolicy { mode: InputMode::PassThrough, wheel: Some(InputMode::BlockWanted), ..Default::default() }`
+#[derive(Clone, Debug, Default)]
+pub struct InputPolicy {
+    /// used for every class without an override
+    pub mode: InputMode,
+    pub keyboard: Option<InputMode>,
+    pub mouse_buttons: Option<InputMode>,
+    pub wheel: Option<InputMode>,
+}
+
+impl InputPolicy {
+    /// blocks all input from the host
+    pub fn modal() -> Self {
+        Self {
+            mode: InputMode::BlockAll,
+            ..Default::default()
+        }
+    }
+
+    /// never blocks any input from the host
+    pub fn passive() -> Self {
+        Self {
+            mode: InputMode::PassThrough,
+            ..Default::default()
+        }
+    }
+
+    /// the mode that applies to a class
+    pub fn mode_for(&self, class: MessageClass) -> &InputMode {
+        let mode = match class {
+            MessageClass::Keyboard => &self.keyboard,
+            MessageClass::MouseButton => &self.mouse_buttons,
+            MessageClass::Wheel => &self.wheel,
+            MessageClass::MouseMove | MessageClass::Touch => &None,
+        };
+
+        mode.as_ref().unwrap_or(&self.mode)
+    }
+
+    /// runs the policy for a message, true means it should be kept from the host
+    pub(crate) fn blocks(&self, message: &RoutedMessage) -> bool {
+        match self.mode_for(message.class) {
+            InputMode::PassThrough => false,
+            InputMode::BlockAll => true,
+            InputMode::BlockWanted => match message.class {
+                MessageClass::Keyboard => message.egui_ctx.wants_keyboard_input(),
+                _ => message.egui_ctx.wants_pointer_input(),
+            },
+            InputMode::Custom(predicate) => predicate(message),
+        }
+    }
+}
+
+/// the class of a regular (non raw input) window message
+pub(crate) fn message_class(umsg: u32) -> Option<MessageClass> {
+    Some(match umsg {
+        WM_KEYDOWN | WM_KEYUP | WM_SYSKEYDOWN | WM_SYSKEYUP | WM_CHAR => MessageClass::Keyboard,
+        WM_MOUSEMOVE => MessageClass::MouseMove,
+        WM_LBUTTONDOWN | WM_LBUTTONDBLCLK | WM_LBUTTONUP | WM_RBUTTONDOWN | WM_RBUTTONDBLCLK
+        | WM_RBUTTONUP | WM_MBUTTONDOWN | WM_MBUTTONDBLCLK | WM_MBUTTONUP | WM_XBUTTONDOWN
+        | WM_XBUTTONDBLCLK | WM_XBUTTONUP => MessageClass::MouseButton,
+        WM_MOUSEWHEEL | WM_MOUSEHWHEEL => MessageClass::Wheel,
+        WM_POINTERDOWN | WM_POINTERUPDATE | WM_POINTERUP => MessageClass::Touch,
+        _ => return None,
+    })
+}
+
+/// anything on_event turns into egui input, which is left alone while the overlay is hidden
+pub(crate) fn is_input_message(umsg: u32) -> bool {
+    message_class(umsg).is_some()
+        || matches!(umsg, WM_INPUT | WM_SETCURSOR | WM_POINTERCAPTURECHANGED)
+}
+
+/// for key and button ups, the message of the matching down; the host has to see the up if it saw the down
+pub(crate) fn release_of(umsg: u32, wparam: usize) -> Option<(u32, usize)> {
+    Some(match umsg {
+        WM_KEYUP | WM_SYSKEYUP => (WM_KEYDOWN, wparam),
+        WM_LBUTTONUP => (WM_LBUTTONDOWN, 0),
+        WM_RBUTTONUP => (WM_RBUTTONDOWN, 0),
+        WM_MBUTTONUP => (WM_MBUTTONDOWN, 0),
+        WM_XBUTTONUP => (WM_XBUTTONDOWN, wparam >> 16),
+        _ => return None,
+    })
+}
+
+/// the key up that ends a key down, for hosts that would otherwise keep seeing a held key
+pub(crate) fn key_up_for(umsg: u32, wparam: usize, lparam: isize) -> Option<EventDisposition> {
+    let umsg = match umsg {
+        WM_KEYDOWN => WM_KEYUP,
+        WM_SYSKEYDOWN => WM_SYSKEYUP,
+        _ => return None,
+    };
+
+    // same scan code and extended flag, repeat count of 1, previous state down, transition state up
+    let lparam = (lparam & 0x01FF_0000 | 1 | 0xC000_0000) as u32 as isize;
+
+    Some(EventDisposition::Forward {
+        umsg,
+        wparam,
+        lparam,
+    })
+}
+
+/// the other half of release_of, identifies a key or button down
+pub(crate) fn press_of(umsg: u32, wparam: usize) -> Option<(u32, usize)> {
+    Some(match umsg {
+        WM_KEYDOWN | WM_SYSKEYDOWN => (WM_KEYDOWN, wparam),
+        WM_LBUTTONDOWN | WM_LBUTTONDBLCLK => (WM_LBUTTONDOWN, 0),
+        WM_RBUTTONDOWN | WM_RBUTTONDBLCLK => (WM_RBUTTONDOWN, 0),
+        WM_MBUTTONDOWN | WM_MBUTTONDBLCLK => (WM_MBUTTONDOWN, 0),
+        WM_XBUTTONDOWN | WM_XBUTTONDBLCLK => (WM_XBUTTONDOWN, wparam >> 16),
+        _ => return None,
+    })
+}
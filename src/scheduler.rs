@@ -0,0 +1,332 @@
+use egui::ClippedPrimitive;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// how often paint runs the ui function
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderMode {
+    /// every paint runs the ui and tessellates, like any immediate mode gui
+    #[default]
+    Immediate,
+    /// the ui only runs when input arrived, the window was resized, or egui asked for a repaint
+    /// (animations, request_repaint from another thread). paints in between redraw the last frame
+    Retained,
+}
+
+/// caps on how often the ui function runs, on top of the RenderMode. frames in between redraw the last one,
+/// and input waits in the queue for the next run
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RateLimit {
+    /// runs per second at most, e.g. 30.0
+    pub max_rate: Option<f32>,
+    /// milliseconds of ui time to spend per host frame on average; the ui runs every few frames
+    /// when a run takes longer than this, so a heavy overlay can't drag down the game's frame rate
+    pub cpu_budget_ms: Option<f32>,
+}
+
+/// decides when the ui runs, and keeps the last frame's meshes to redraw in between
+pub(crate) struct Scheduler {
+    /// what the last run tessellated, along with the pixels_per_point and window size it was for
+    cached: Option<(Vec<ClippedPrimitive>, f32, (u32, u32))>,
+    /// the ui ran since cached was stored, without anything to draw into
+    stale: bool,
+    /// clock time of the next repaint egui asked for in the last run's output
+    repaint_at: f64,
+    /// clock time of the soonest repaint asked for through request_repaint since the last run started
+    requested_at: f64,
+    /// the shortest delay asked for through request_repaint since should_run last looked, shared with egui's callback
+    requested: Arc<Mutex<Option<Duration>>>,
+    last_run: Option<f64>,
+    /// paints that redrew the cache since the last run
+    skipped: u32,
+    /// smoothed milliseconds a run takes
+    run_cost_ms: f32,
+}
+
+impl Scheduler {
+    /// hooks into egui_ctx so request_repaint from any thread reaches the next paint
+    pub(crate) fn new(egui_ctx: &egui::Context) -> Self {
+        let requested = Arc::new(Mutex::new(None::<Duration>));
+
+        let callback_requested = requested.clone();
+        egui_ctx.set_request_repaint_callback(move |info| {
+            if let Ok(mut requested) = callback_requested.lock() {
+                *requested = Some(requested.map_or(info.delay, |delay| delay.min(info.delay)));
+            }
+        });
+
+        Self {
+            cached: None,
+            stale: false,
+            repaint_at: 0.0,
+            requested_at: f64::INFINITY,
+            requested,
+            last_run: None,
+            skipped: 0,
+            run_cost_ms: 0.0,
+        }
+    }
+
+    /// if the ui has to run this paint; size is None while there is nothing to draw into
+    pub(crate) fn should_run(
+        &mut self,
+        mode: RenderMode,
+        limit: RateLimit,
+        now: f64,
+        has_input: bool,
+        size: Option<(u32, u32)>,
+    ) -> bool {
+        // a request from another thread counts from when it's noticed here, at most a frame late
+        if let Some(delay) = self.requested.lock().ok().and_then(|mut r| r.take()) {
+            self.requested_at = self.requested_at.min(now + delay.as_secs_f64());
+        }
+
+        // nothing to redraw, or a redraw would come out wrong. while minimized the usual limits apply
+        if let Some(size) = size {
+            match &self.cached {
+                Some((_, _, cached_size)) if *cached_size == size && !self.stale => {}
+                _ => return true,
+            }
+        }
+
+        let due = match mode {
+            RenderMode::Immediate => true,
+            RenderMode::Retained => has_input || now >= self.repaint_at.min(self.requested_at),
+        };
+
+        if due && self.allows(limit, now) {
+            return true;
+        }
+
+        self.skipped += 1;
+
+        false
+    }
+
+    fn allows(&self, limit: RateLimit, now: f64) -> bool {
+        let rate_allows = match (limit.max_rate, self.last_run) {
+            (Some(max_rate), Some(last_run)) if max_rate > 0.0 => {
+                now - last_run >= 1.0 / max_rate as f64
+            }
+            _ => true,
+        };
+
+        // spread each run's cost over enough frames to stay within budget on average
+        let budget_allows = match limit.cpu_budget_ms {
+            Some(budget) if budget > 0.0 => {
+                (self.skipped + 1) as f32 >= (self.run_cost_ms / budget).ceil()
+            }
+            _ => true,
+        };
+
+        rate_allows && budget_allows
+    }
+
+    /// call right before a run; it takes care of anything requested up to now. requests after this
+    /// are kept, even when the run finishes much later on the ui thread
+    pub(crate) fn started(&mut self) {
+        if let Ok(mut requested) = self.requested.lock() {
+            *requested = None;
+        }
+
+        self.requested_at = f64::INFINITY;
+    }
+
+    /// records a finished run; delay is egui's repaint_delay for the root viewport
+    pub(crate) fn ran(&mut self, now: f64, delay: Duration) {
+        self.repaint_at = now + delay.as_secs_f64();
+        self.last_run = Some(now);
+        self.skipped = 0;
+    }
+
+    /// records how long a run took, in real time no matter what clock egui uses
+    pub(crate) fn record_cost(&mut self, cost: Duration) {
+        let cost_ms = cost.as_secs_f32() * 1000.0;
+
+        // quick to back off from a slow frame, slow to trust a fast one
+        let smoothing = if cost_ms > self.run_cost_ms { 0.5 } else { 0.1 };
+        self.run_cost_ms += (cost_ms - self.run_cost_ms) * smoothing;
+    }
+
+    /// smoothed milliseconds a run takes
+    pub(crate) fn run_cost_ms(&self) -> f32 {
+        self.run_cost_ms
+    }
+
+    pub(crate) fn store(
+        &mut self,
+        primitives: Vec<ClippedPrimitive>,
+        pixels_per_point: f32,
+        size: (u32, u32),
+    ) {
+        self.cached = Some((primitives, pixels_per_point, size));
+        self.stale = false;
+    }
+
+    /// for runs that weren't tessellated, the next paint that can draw runs the ui again
+    pub(crate) fn mark_stale(&mut self) {
+        self.stale = true;
+    }
+
+    /// forgets the last frame, so the next paint runs the ui again
+    pub(crate) fn invalidate(&mut self) {
+        self.cached = None;
+    }
+
+    pub(crate) fn cached(&self) -> Option<(&[ClippedPrimitive], f32)> {
+        self.cached
+            .as_ref()
+            .map(|(primitives, pixels_per_point, _)| (primitives.as_slice(), *pixels_per_point))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: Option<(u32, u32)> = Some((800, 600));
+
+    /// a scheduler that has drawn one frame at time 0, with egui not asking for another
+    fn scheduler() -> Scheduler {
+        let mut scheduler = Scheduler::new(&egui::Context::default());
+        scheduler.ran(0.0, Duration::MAX);
+        scheduler.store(Vec::new(), 1.0, (800, 600));
+
+        scheduler
+    }
+
+    fn run(scheduler: &mut Scheduler, limit: RateLimit, now: f64) -> bool {
+        scheduler.should_run(RenderMode::Immediate, limit, now, false, SIZE)
+    }
+
+    #[test]
+    fn runs_without_a_matching_cache() {
+        let mut fresh = Scheduler::new(&egui::Context::default());
+        assert!(fresh.should_run(RenderMode::Retained, RateLimit::default(), 0.0, false, SIZE));
+
+        let mut scheduler = scheduler();
+        let resized = Some((1024, 768));
+        assert!(scheduler.should_run(
+            RenderMode::Retained,
+            RateLimit::default(),
+            1.0,
+            false,
+            resized
+        ));
+
+        scheduler.mark_stale();
+        assert!(scheduler.should_run(RenderMode::Retained, RateLimit::default(), 1.0, false, SIZE));
+    }
+
+    #[test]
+    fn retained_waits_for_input_or_repaint() {
+        let mut scheduler = scheduler();
+
+        assert!(!scheduler.should_run(
+            RenderMode::Retained,
+            RateLimit::default(),
+            1.0,
+            false,
+            SIZE
+        ));
+        assert!(scheduler.should_run(RenderMode::Retained, RateLimit::default(), 1.0, true, SIZE));
+
+        scheduler.ran(1.0, Duration::from_millis(500));
+        assert!(!scheduler.should_run(
+            RenderMode::Retained,
+            RateLimit::default(),
+            1.4,
+            false,
+            SIZE
+        ));
+        assert!(scheduler.should_run(RenderMode::Retained, RateLimit::default(), 1.5, false, SIZE));
+    }
+
+    #[test]
+    fn requests_during_a_run_are_kept() {
+        let egui_ctx = egui::Context::default();
+        let mut scheduler = Scheduler::new(&egui_ctx);
+        scheduler.ran(0.0, Duration::MAX);
+        scheduler.store(Vec::new(), 1.0, (800, 600));
+
+        assert!(scheduler.should_run(RenderMode::Retained, RateLimit::default(), 1.0, true, SIZE));
+        scheduler.started();
+
+        // another thread asks while the run is still going
+        egui_ctx.request_repaint_after(Duration::from_millis(100));
+        assert!(!scheduler.should_run(
+            RenderMode::Retained,
+            RateLimit::default(),
+            1.05,
+            false,
+            SIZE
+        ));
+
+        // the run finishing without wanting a repaint of its own doesn't cancel the request
+        scheduler.ran(1.0, Duration::MAX);
+        assert!(scheduler.should_run(RenderMode::Retained, RateLimit::default(), 1.2, false, SIZE));
+    }
+
+    #[test]
+    fn nothing_to_draw_keeps_limits() {
+        let mut scheduler = scheduler();
+
+        // a minimized window's size doesn't match the cache, but that's no reason to run
+        assert!(!scheduler.should_run(
+            RenderMode::Retained,
+            RateLimit::default(),
+            1.0,
+            false,
+            None
+        ));
+
+        let limit = RateLimit {
+            max_rate: Some(10.0),
+            ..Default::default()
+        };
+        assert!(!scheduler.should_run(RenderMode::Immediate, limit, 0.05, false, None));
+        assert!(scheduler.should_run(RenderMode::Immediate, limit, 0.1, false, None));
+    }
+
+    #[test]
+    fn max_rate_spaces_runs() {
+        let mut scheduler = scheduler();
+        let limit = RateLimit {
+            max_rate: Some(10.0),
+            ..Default::default()
+        };
+
+        assert!(!run(&mut scheduler, limit, 0.05));
+        assert!(run(&mut scheduler, limit, 0.1));
+
+        scheduler.ran(0.1, Duration::MAX);
+        assert!(!run(&mut scheduler, limit, 0.15));
+        assert!(run(&mut scheduler, limit, 0.25));
+    }
+
+    #[test]
+    fn cpu_budget_spreads_expensive_runs() {
+        let mut scheduler = scheduler();
+        let limit = RateLimit {
+            cpu_budget_ms: Some(5.0),
+            ..Default::default()
+        };
+
+        // backing off from a slow run is quick, half way there in one
+        scheduler.record_cost(Duration::from_millis(20));
+        assert_eq!(scheduler.run_cost_ms(), 10.0);
+
+        // 10ms a run on a 5ms budget is every other frame
+        assert!(!run(&mut scheduler, limit, 1.0));
+        assert!(run(&mut scheduler, limit, 1.1));
+
+        scheduler.ran(1.1, Duration::MAX);
+        assert!(!run(&mut scheduler, limit, 1.2));
+        assert!(run(&mut scheduler, limit, 1.3));
+
+        // without a budget every frame runs
+        assert!(run(&mut scheduler, RateLimit::default(), 1.4));
+    }
+}
@@ -0,0 +1,187 @@
+use egui::{ClippedPrimitive, CursorIcon, PlatformOutput, RawInput, TexturesDelta, ViewportId};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// what the render thread hands the ui thread for one run
+pub(crate) struct Request {
+    pub(crate) raw_input: RawInput,
+    pub(crate) draw_cursor: bool,
+    pub(crate) cursor_icon: CursorIcon,
+    /// clock time the input was gathered at
+    pub(crate) now: f64,
+    /// the window size the frame is for
+    pub(crate) dimensions: (u32, u32),
+}
+
+impl Request {
+    /// folds a newer request into one the ui thread hasn't picked up yet, so no input is lost
+    fn merge(&mut self, mut newer: Request) {
+        let mut events = std::mem::take(&mut self.raw_input.events);
+        events.append(&mut newer.raw_input.events);
+        newer.raw_input.events = events;
+
+        let mut dropped_files = std::mem::take(&mut self.raw_input.dropped_files);
+        dropped_files.append(&mut newer.raw_input.dropped_files);
+        newer.raw_input.dropped_files = dropped_files;
+
+        *self = newer;
+    }
+}
+
+/// a finished run, everything the render thread needs to draw it
+pub(crate) struct Frame {
+    pub(crate) platform_output: PlatformOutput,
+    pub(crate) textures_delta: TexturesDelta,
+    pub(crate) primitives: Vec<ClippedPrimitive>,
+    pub(crate) pixels_per_point: f32,
+    pub(crate) repaint_delay: Duration,
+    pub(crate) now: f64,
+    pub(crate) dimensions: (u32, u32),
+    /// how long the run and tessellation took
+    pub(crate) cost: Duration,
+}
+
+impl Frame {
+    /// folds a newer frame into one the render thread hasn't drawn yet; texture changes and
+    /// platform output can't be skipped, the meshes can
+    fn merge(&mut self, mut newer: Frame) {
+        self.textures_delta
+            .append(std::mem::take(&mut newer.textures_delta));
+        newer.textures_delta = std::mem::take(&mut self.textures_delta);
+
+        let mut platform_output = std::mem::take(&mut self.platform_output);
+        platform_output.append(std::mem::take(&mut newer.platform_output));
+        newer.platform_output = platform_output;
+
+        *self = newer;
+    }
+}
+
+/// one slot each way: the latest input for the ui thread, the latest frame for the render thread
+#[derive(Default)]
+struct Shared {
+    request: Mutex<Option<Request>>,
+    frame: Mutex<Option<Frame>>,
+    wake: Condvar,
+    stop: AtomicBool,
+}
+
+/// runs egui and tessellates on its own thread. that thread never touches gl,
+/// textures are uploaded by the render thread when it takes a frame
+pub(crate) struct UiThread {
+    shared: Arc<Shared>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl UiThread {
+    pub(crate) fn spawn(
+        egui_ctx: egui::Context,
+        mut run_fn: Box<dyn FnMut(&egui::Context) + Send>,
+    ) -> Self {
+        let shared = Arc::new(Shared::default());
+        let thread_shared = shared.clone();
+
+        let handle = std::thread::spawn(move || {
+            while let Some(request) = wait_for_request(&thread_shared) {
+                let started = Instant::now();
+
+                let output = crate::run_egui(
+                    &egui_ctx,
+                    request.raw_input,
+                    &mut run_fn,
+                    request.draw_cursor,
+                    request.cursor_icon,
+                );
+                let primitives = egui_ctx.tessellate(output.shapes, output.pixels_per_point);
+
+                let frame = Frame {
+                    platform_output: output.platform_output,
+                    textures_delta: output.textures_delta,
+                    primitives,
+                    pixels_per_point: output.pixels_per_point,
+                    repaint_delay: output
+                        .viewport_output
+                        .get(&ViewportId::ROOT)
+                        .map_or(Duration::MAX, |viewport| viewport.repaint_delay),
+                    now: request.now,
+                    dimensions: request.dimensions,
+                    cost: started.elapsed(),
+                };
+
+                let Ok(mut slot) = thread_shared.frame.lock() else {
+                    return;
+                };
+                match slot.as_mut() {
+                    Some(undrawn) => undrawn.merge(frame),
+                    None => *slot = Some(frame),
+                }
+            }
+        });
+
+        Self {
+            shared,
+            handle: Some(handle),
+        }
+    }
+
+    /// queues input for the next run without waiting for the current one
+    pub(crate) fn post(&self, request: Request) {
+        if let Ok(mut slot) = self.shared.request.lock() {
+            match slot.as_mut() {
+                Some(pending) => pending.merge(request),
+                None => *slot = Some(request),
+            }
+        }
+
+        self.shared.wake.notify_one();
+    }
+
+    /// false once the thread is gone, which short of Drop means the ui function panicked
+    pub(crate) fn is_running(&self) -> bool {
+        self.handle
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+    }
+
+    /// the newest finished frame, if one came in since the last call
+    pub(crate) fn take_frame(&self) -> Option<Frame> {
+        self.shared.frame.lock().ok()?.take()
+    }
+}
+
+impl Drop for UiThread {
+    fn drop(&mut self) {
+        // set under the lock, so the ui thread can't miss the wake between checking stop and waiting
+        if let Ok(_slot) = self.shared.request.lock() {
+            self.shared.stop.store(true, Ordering::Relaxed);
+        }
+        self.shared.wake.notify_one();
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// blocks until there is input to run, None once the thread should stop
+fn wait_for_request(shared: &Shared) -> Option<Request> {
+    let mut slot = shared.request.lock().ok()?;
+
+    loop {
+        if shared.stop.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        if let Some(request) = slot.take() {
+            return Some(request);
+        }
+
+        slot = shared.wake.wait(slot).ok()?;
+    }
+}
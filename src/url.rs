@@ -0,0 +1,200 @@
+use egui::OpenUrl;
+use std::sync::Arc;
+
+#[cfg(windows)]
+use windows::{
+    core::{w, HSTRING},
+    Win32::{
+        Foundation::HWND,
+        UI::{
+            Shell::ShellExecuteW,
+            WindowsAndMessaging::{
+                MessageBoxW, IDYES, MB_ICONWARNING, MB_SETFOREGROUND, MB_TOPMOST, MB_YESNO,
+                SW_SHOWNORMAL,
+            },
+        },
+    },
+};
+
+/// opens a url that a hyperlink asked for, after the UrlPolicy let it through
+pub type UrlHandler = Arc<dyn Fn(&OpenUrl) + Send + Sync>;
+
+/// which hyperlinks get opened; the overlay runs in someone else's process, so nothing is opened silently by default
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum UrlPolicy {
+    Deny,
+    /// any http or https url
+    Allow,
+    /// only urls under one of these, e.g. "https://wiki.example.com/guides". the scheme and host have to match
+    /// exactly and the path has to start with the entry's up to a '/'. any scheme can be listed
+    AllowList(Vec<String>),
+    /// asks with a message box before opening an http or https url
+    #[default]
+    Confirm,
+}
+
+/// applies policy and hands the url to handler, off the calling thread so prompts and slow shells don't stall a frame
+pub(crate) fn open(policy: &UrlPolicy, handler: UrlHandler, open_url: OpenUrl) {
+    let confirm = match policy {
+        UrlPolicy::Deny => return,
+        UrlPolicy::Allow | UrlPolicy::Confirm if !is_web_url(&open_url.url) => return,
+        UrlPolicy::Allow => false,
+        UrlPolicy::AllowList(entries) => {
+            if !entries.iter().any(|entry| is_listed(entry, &open_url.url)) {
+                return;
+            }
+
+            false
+        }
+        UrlPolicy::Confirm => true,
+    };
+
+    std::thread::spawn(move || {
+        if !confirm || confirm_open(&open_url.url) {
+            handler(&open_url);
+        }
+    });
+}
+
+/// the default handler, the default browser on windows and xdg-open elsewhere
+pub fn open_in_browser(open_url: &OpenUrl) {
+    #[cfg(windows)]
+    unsafe {
+        ShellExecuteW(
+            HWND(0),
+            w!("open"),
+            &HSTRING::from(open_url.url.as_str()),
+            None,
+            None,
+            SW_SHOWNORMAL,
+        );
+    }
+
+    #[cfg(not(windows))]
+    let _ = std::process::Command::new("xdg-open")
+        .arg(&open_url.url)
+        .spawn();
+}
+
+/// the parts of a url AllowList compares, with the scheme and host lowercased
+struct UrlParts<'a> {
+    scheme: String,
+    host: String,
+    /// everything after the host, query and fragment included
+    path: &'a str,
+}
+
+fn split_url(url: &str) -> Option<UrlParts<'_>> {
+    let (scheme, rest) = url.split_once(':')?;
+
+    // mailto: and such have no host, everything after the scheme counts as the path
+    let Some(rest) = rest.strip_prefix("//") else {
+        return Some(UrlParts {
+            scheme: scheme.to_ascii_lowercase(),
+            host: String::new(),
+            path: rest,
+        });
+    };
+
+    // browsers end the host at a backslash too
+    let end = rest.find(['/', '\\', '?', '#']).unwrap_or(rest.len());
+    let (authority, path) = rest.split_at(end);
+    // skips user:password@, which is how "https://wiki.example.com@attacker.io" gets to attacker.io
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+
+    Some(UrlParts {
+        scheme: scheme.to_ascii_lowercase(),
+        host: host.to_ascii_lowercase(),
+        path,
+    })
+}
+
+/// same scheme and host, and a path under the entry's; a plain prefix would let
+/// "https://wiki.example.com" through to "https://wiki.example.com.attacker.io"
+fn is_listed(entry: &str, url: &str) -> bool {
+    let (Some(entry), Some(url)) = (split_url(entry), split_url(url)) else {
+        return false;
+    };
+
+    if entry.scheme != url.scheme || entry.host != url.host {
+        return false;
+    }
+
+    let Some(rest) = url.path.strip_prefix(entry.path) else {
+        return false;
+    };
+
+    // an entry without a path covers the whole host, or the whole scheme for ones like "mailto:"
+    entry.path.is_empty()
+        || entry.path.ends_with('/')
+        || rest.is_empty()
+        || rest.starts_with(['/', '\\', '?', '#'])
+}
+
+fn is_web_url(url: &str) -> bool {
+    let lower = url.to_ascii_lowercase();
+
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+#[cfg(windows)]
+fn confirm_open(url: &str) -> bool {
+    let text = HSTRING::from(format!("Open this link in your browser?\n\n{url}"));
+
+    unsafe {
+        MessageBoxW(
+            HWND(0),
+            &text,
+            w!("Open link"),
+            MB_YESNO | MB_ICONWARNING | MB_TOPMOST | MB_SETFOREGROUND,
+        ) == IDYES
+    }
+}
+
+/// nothing to ask with, so nothing gets opened
+#[cfg(not(windows))]
+fn confirm_open(_url: &str) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allow_list_matches_whole_hosts() {
+        let entry = "https://wiki.example.com";
+
+        assert!(is_listed(entry, "https://wiki.example.com"));
+        assert!(is_listed(entry, "https://wiki.example.com/guides?page=2"));
+        assert!(is_listed(entry, "HTTPS://Wiki.Example.com/"));
+        assert!(!is_listed(entry, "https://wiki.example.com.attacker.io/"));
+        assert!(!is_listed(entry, "https://wiki.example.com@attacker.io/"));
+        assert!(!is_listed(entry, "https://wiki.example.company/"));
+        assert!(!is_listed(entry, "http://wiki.example.com/"));
+    }
+
+    #[test]
+    fn allow_list_paths_end_at_a_slash() {
+        let entry = "https://example.com/guides";
+
+        assert!(is_listed(entry, "https://example.com/guides"));
+        assert!(is_listed(entry, "https://example.com/guides/start#top"));
+        assert!(!is_listed(entry, "https://example.com/guides-evil"));
+        assert!(!is_listed(entry, "https://example.com/"));
+
+        assert!(is_listed(
+            "https://example.com/guides/",
+            "https://example.com/guides/start"
+        ));
+    }
+
+    #[test]
+    fn allow_list_takes_other_schemes() {
+        assert!(is_listed("mailto:", "mailto:support@example.com"));
+        assert!(!is_listed("mailto:", "https://example.com"));
+        assert!(!is_listed("https://example.com", "javascript:alert(1)"));
+    }
+}